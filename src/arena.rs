@@ -0,0 +1,708 @@
+use crate::{
+    error::{KResult, KikanError},
+    handler::LocalHandle,
+    kikan::{Kikan, Position, Rules, Unit, UnitId},
+    map::{Map, Spawn},
+    remote::serve_tcp,
+    render::Film,
+    scheduler::Scheduler,
+    script::{load_lua_script_with, ScriptConfig},
+    topology::Topology,
+};
+use rand::{seq::index, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use std::{
+    collections::BTreeSet,
+    fs::File,
+    io::BufWriter,
+    net::TcpStream,
+    path::PathBuf,
+    sync::{Arc, Condvar, Mutex},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+#[derive(Debug, Clone)]
+pub struct ArenaConfig {
+    pub seed: u64,
+    pub max_ticks: usize,
+    /// units start inside a `field_size` x `field_size` square, unless there is a map
+    pub field_size: u32,
+    /// units start on free cells of the map and never leave it
+    pub map: Option<Map>,
+    /// the first script starts on the first spawn and so on, empty to draw start positions
+    pub spawns: Vec<Spawn>,
+    pub topology: Topology,
+    /// lua instructions a script may run per tick, `None` for no limit
+    pub turn_budget: Option<u32>,
+    /// `seed` is replaced by one drawn from the arena seed
+    pub script: ScriptConfig,
+    pub rules: Rules,
+    /// replay file written during the match
+    pub record: Option<PathBuf>,
+    /// keep a [`Film`] of the match in the result
+    pub film: bool,
+    /// how long a remote bot may think before it is dropped, `None` to wait forever
+    pub remote_timeout: Option<Duration>,
+}
+
+impl Default for ArenaConfig {
+    fn default() -> Self {
+        Self {
+            seed: 0,
+            max_ticks: 1000,
+            field_size: 16,
+            map: None,
+            spawns: Vec::new(),
+            topology: Topology::default(),
+            turn_budget: Some(100_000),
+            script: ScriptConfig::default(),
+            rules: Rules::default(),
+            record: None,
+            film: false,
+            remote_timeout: Some(Duration::from_secs(5)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnitReport {
+    pub id: UnitId,
+    pub name: Option<String>,
+    pub team: Option<String>,
+    pub position: Position,
+    pub health: u32,
+    /// damage dealt to other units
+    pub score: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArenaResult {
+    pub ticks: usize,
+    pub survivors: Vec<UnitId>,
+    pub units: Vec<UnitReport>,
+    /// scripts that stopped with an error during the match, by name
+    pub script_errors: Vec<(String, String)>,
+    /// ticks where a commit failed to resolve, the rest of the tick still played
+    pub update_errors: Vec<(usize, String)>,
+    pub film: Option<Film>,
+    /// the replay stopped early, see [`Kikan::recording_failed`]
+    pub recording_failed: Option<String>,
+}
+
+impl ArenaResult {
+    pub fn winner(&self) -> Option<&UnitReport> {
+        match self.survivors.as_slice() {
+            [winner] => self.units.iter().find(|unit| unit.id == *winner),
+            _ => None,
+        }
+    }
+
+    /// The team of the survivors, if they all have the same one.
+    pub fn winning_team(&self) -> Option<&str> {
+        let mut teams = self
+            .units
+            .iter()
+            .filter(|unit| self.survivors.contains(&unit.id))
+            .map(|unit| unit.team.as_deref());
+        let first = teams.next()??;
+        teams.all(|team| team == Some(first)).then_some(first)
+    }
+}
+
+struct PaceState {
+    delay: Duration,
+    paused: bool,
+    /// ticks to play while paused
+    steps: usize,
+    stopped: bool,
+}
+
+/// Lets a spectator hold, step, slow down and stop a match.
+/// Clones control the same match.
+#[derive(Clone)]
+pub struct Pace(Arc<(Mutex<PaceState>, Condvar)>);
+
+impl Pace {
+    /// Plays a tick every `delay`.
+    pub fn new(delay: Duration) -> Self {
+        let state = PaceState {
+            delay,
+            paused: false,
+            steps: 0,
+            stopped: false,
+        };
+        Self(Arc::new((Mutex::new(state), Condvar::new())))
+    }
+
+    fn change(&self, change: impl FnOnce(&mut PaceState)) {
+        change(&mut self.0 .0.lock().unwrap());
+        self.0 .1.notify_all();
+    }
+
+    pub fn set_paused(&self, paused: bool) {
+        self.change(|state| state.paused = paused);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.0 .0.lock().unwrap().paused
+    }
+
+    /// Plays one more tick while paused.
+    pub fn step(&self) {
+        self.change(|state| state.steps += 1);
+    }
+
+    pub fn set_delay(&self, delay: Duration) {
+        self.change(|state| state.delay = delay);
+    }
+
+    pub fn delay(&self) -> Duration {
+        self.0 .0.lock().unwrap().delay
+    }
+
+    /// Ends the match before the next tick.
+    pub fn stop(&self) {
+        self.change(|state| state.stopped = true);
+    }
+
+    /// Waits until the next tick may be played, `false` once stopped.
+    fn wait(&self) -> bool {
+        let (state, wake) = &*self.0;
+        let mut state = state.lock().unwrap();
+        loop {
+            if state.stopped {
+                return false;
+            }
+            if !state.paused {
+                let delay = state.delay;
+                drop(state);
+                thread::sleep(delay);
+                return true;
+            }
+            if state.steps > 0 {
+                state.steps -= 1;
+                return true;
+            }
+            state = wake.wait(state).unwrap();
+        }
+    }
+}
+
+enum Bot {
+    Lua(String),
+    Remote(TcpStream),
+}
+
+/// A reproducible match: scripts and world take turns, one tick at a time.
+pub struct Arena {
+    config: ArenaConfig,
+    bots: Vec<(String, Bot)>,
+    kikan: Arc<Mutex<Kikan>>,
+    pace: Option<Pace>,
+}
+
+impl Arena {
+    pub fn new(config: ArenaConfig) -> Self {
+        Self {
+            config,
+            bots: Vec::new(),
+            kikan: Kikan::kikan_in_a_shell(|| Position(0, 0)),
+            pace: None,
+        }
+    }
+
+    /// The world of the match, to listen to its updates.
+    /// It is set up from the config once the match runs.
+    pub fn world(&self) -> Arc<Mutex<Kikan>> {
+        Arc::clone(&self.kikan)
+    }
+
+    /// Plays ticks as `pace` allows instead of as fast as possible.
+    pub fn set_pace(&mut self, pace: Pace) -> &mut Self {
+        self.pace = Some(pace);
+        self
+    }
+
+    pub fn add_script(&mut self, name: String, script: String) -> &mut Self {
+        self.bots.push((name, Bot::Lua(script)));
+        self
+    }
+
+    /// A unit played over `stream`, see [`crate::remote`].
+    pub fn add_remote(&mut self, name: String, stream: TcpStream) -> &mut Self {
+        self.bots.push((name, Bot::Remote(stream)));
+        self
+    }
+
+    /// Where each script starts: the configured spawns, or positions drawn from the seed.
+    fn start_positions(&self) -> KResult<Vec<Spawn>> {
+        let number = self.bots.len();
+        if !self.config.spawns.is_empty() {
+            if number > self.config.spawns.len() {
+                return Err(KikanError::NoRoomForUnits(number));
+            }
+            return Ok(self.config.spawns[..number].to_vec());
+        }
+        let cells = match &self.config.map {
+            Some(map) => map.free_cells(),
+            None => {
+                let size = self.config.field_size as i32;
+                (0..size).flat_map(|x| (0..size).map(move |y| Position(x, y))).collect()
+            }
+        };
+        if number > cells.len() {
+            return Err(KikanError::NoRoomForUnits(number));
+        }
+        let mut rng = ChaCha8Rng::seed_from_u64(self.config.seed);
+        Ok(index::sample(&mut rng, cells.len(), number)
+            .into_iter()
+            .map(|i| Spawn {
+                pos: cells[i],
+                team: None,
+            })
+            .collect())
+    }
+
+    pub fn run(self) -> KResult<ArenaResult> {
+        let spawns = self.start_positions()?;
+        let kikan = self.kikan;
+        let mut film = None;
+        {
+            let mut kikan = kikan.lock().unwrap();
+            kikan.set_rules(self.config.rules);
+            kikan.set_topology(self.config.topology);
+            if let Some(map) = self.config.map {
+                kikan.set_map(map);
+            }
+            if let Some(path) = &self.config.record {
+                kikan.record_to(Box::new(BufWriter::new(File::create(path)?)))?;
+            }
+            if self.config.film {
+                film = Some(Film::new(&kikan));
+            }
+        }
+
+        let mut scheduler = Scheduler::with_turn_budget(self.config.turn_budget);
+        let remote_timeout = self.config.remote_timeout;
+        let runners: Vec<(String, JoinHandle<KResult<()>>)> = self
+            .bots
+            .into_iter()
+            .zip(spawns)
+            .enumerate()
+            .map(|(i, ((name, bot), spawn))| {
+                let turn = scheduler.take_seat();
+                let mut origin = Unit::builder();
+                origin.set_name(name.clone()).set_start(spawn.pos);
+                if let Some(team) = spawn.team {
+                    origin.set_team(team);
+                }
+                let kikan = Arc::clone(&kikan);
+                let config = ScriptConfig {
+                    seed: self.config.seed ^ (i as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15),
+                    ..self.config.script.clone()
+                };
+                let runner = thread::spawn(move || {
+                    turn.wait()?;
+                    let handler = LocalHandle::with_origin(kikan, origin).with_turn(turn);
+                    match bot {
+                        Bot::Lua(script) => load_lua_script_with(handler, script, &config),
+                        Bot::Remote(stream) => {
+                            // a silent or deaf bot would hold up everyone
+                            stream.set_read_timeout(remote_timeout)?;
+                            stream.set_write_timeout(remote_timeout)?;
+                            serve_tcp(handler, stream)
+                        }
+                    }
+                });
+                (name, runner)
+            })
+            .collect();
+        let number = runners.len();
+
+        let mut ticks = 0;
+        let mut update_errors = Vec::new();
+        while ticks < self.config.max_ticks && !scheduler.all_left() {
+            if self.pace.as_ref().is_some_and(|pace| !pace.wait()) {
+                break;
+            }
+            scheduler.play_round();
+            let mut kikan = kikan.lock().unwrap();
+            let res = kikan.update();
+            if let Some(film) = film.as_mut() {
+                film.shoot(&kikan);
+            }
+            ticks += 1;
+            if let Err(e) = res {
+                update_errors.push((ticks, e.to_string()));
+            }
+            let ids = kikan.unit_ids();
+            // units without a team fight on their own
+            let sides: BTreeSet<(Option<&str>, Option<UnitId>)> = ids
+                .iter()
+                .filter(|id| kikan.is_unit_alive(**id))
+                .filter_map(|id| kikan.get_unit(*id).map(|unit| (id, unit)))
+                .map(|(id, unit)| match unit.team() {
+                    Some(team) => (Some(team), None),
+                    None => (None, Some(*id)),
+                })
+                .collect();
+            if number > 1 && ids.len() == number && sides.len() <= 1 {
+                break;
+            }
+        }
+
+        // every script is waiting for a turn or done, dropping the seats ends them
+        let left = scheduler.left();
+        drop(scheduler);
+        let mut script_errors = Vec::new();
+        for ((name, runner), left) in runners.into_iter().zip(left) {
+            let res = runner.join().unwrap_or(Err(KikanError::MatchOver));
+            // scripts still playing are stopped by the end of the match
+            if let (true, Err(e)) = (left, res) {
+                script_errors.push((name, e.to_string()));
+            }
+        }
+
+        let kikan = kikan.lock().unwrap();
+        let units: Vec<UnitReport> = kikan
+            .unit_ids()
+            .into_iter()
+            .filter_map(|id| kikan.get_unit(id).map(|unit| (id, unit)))
+            .map(|(id, unit)| UnitReport {
+                id,
+                name: unit.name().map(String::from),
+                team: unit.team().map(String::from),
+                position: unit.position(),
+                health: unit.health(),
+                score: unit.damage_dealt(),
+            })
+            .collect();
+        let survivors = units
+            .iter()
+            .filter(|unit| unit.health > 0)
+            .map(|unit| unit.id)
+            .collect();
+        Ok(ArenaResult {
+            ticks,
+            survivors,
+            units,
+            script_errors,
+            update_errors,
+            film,
+            recording_failed: kikan.recording_failed().map(String::from),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::file::MapFile;
+    use serde_json::Value;
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::TcpListener,
+    };
+
+    const HUNTER: &str = r#"
+        api:set_engine(utils:new_engine("ste"))
+        api:add_mod(utils:new_mod("autocannon"), "gun")
+        api:init()
+        local me = api:get_position()
+        local targets = {}
+        for x = 0, 1 do
+            for y = 0, 1 do
+                if x ~= me.x or y ~= me.y then
+                    table.insert(targets, utils:new_position(x, y))
+                end
+            end
+        end
+        local i = 1
+        while true do
+            if pcall(function() api:mod_on("gun", targets[i]) end) then
+                i = i % #targets + 1
+            end
+            api:wait_for_update()
+        end
+    "#;
+
+    const SITTER: &str = r#"
+        api:set_engine(utils:new_engine("ste"))
+        api:init()
+        while true do
+            api:wait_for_update()
+        end
+    "#;
+
+    fn arena(seed: u64) -> Arena {
+        let mut arena = Arena::new(ArenaConfig {
+            seed,
+            max_ticks: 300,
+            field_size: 2,
+            ..ArenaConfig::default()
+        });
+        arena
+            .add_script("hunter".to_string(), HUNTER.to_string())
+            .add_script("sitter_0".to_string(), SITTER.to_string())
+            .add_script("sitter_1".to_string(), SITTER.to_string());
+        arena
+    }
+
+    #[test]
+    fn same_seed_same_result() {
+        let first = arena(7).run().unwrap();
+        let second = arena(7).run().unwrap();
+        assert_eq!(first, second);
+        assert_eq!(first.units.len(), 3);
+        assert_eq!(first.winner().unwrap().name.as_deref(), Some("hunter"));
+        assert!(first.ticks < 300);
+    }
+
+    #[test]
+    fn paced() {
+        let mut arena = arena(7);
+        let pace = Pace::new(Duration::ZERO);
+        pace.set_paused(true);
+        arena.set_pace(pace.clone());
+        let mut updates = arena.world().lock().unwrap().wait_for_update();
+        let run = thread::spawn(move || arena.run());
+        for tick in 1..=3 {
+            pace.step();
+            assert_eq!(updates.recv().unwrap().tick, tick);
+        }
+        assert!(pace.is_paused());
+        pace.stop();
+        let result = run.join().unwrap().unwrap();
+        assert_eq!(result.ticks, 3);
+        assert!(result.units.iter().all(|unit| unit.health > 0));
+    }
+
+    #[test]
+    fn seeded_start_positions() {
+        let first = arena(1).start_positions().unwrap();
+        assert_eq!(first, arena(1).start_positions().unwrap());
+        assert_ne!(first, arena(2).start_positions().unwrap());
+    }
+
+    #[test]
+    fn scripts_done() {
+        let mut arena = Arena::new(ArenaConfig::default());
+        arena.add_script(
+            "walker".to_string(),
+            r#"
+                api:set_engine(utils:new_engine("ste"))
+                api:init()
+                api:plan_move('N')
+                while api:is_moving() do
+                    api:wait_for_update()
+                end
+            "#
+            .to_string(),
+        );
+        let result = arena.run().unwrap();
+        assert_eq!(result.ticks, 12);
+        assert!(result.script_errors.is_empty());
+        assert_eq!(result.winner().unwrap().name.as_deref(), Some("walker"));
+    }
+
+    #[test]
+    fn remote_bot() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let bot = thread::spawn(move || {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            let mut input = BufReader::new(stream.try_clone().unwrap());
+            let mut call = |line: &str| {
+                writeln!(stream, "{}", line).unwrap();
+                let mut reply = String::new();
+                input.read_line(&mut reply).unwrap();
+                serde_json::from_str::<Value>(&reply).unwrap()
+            };
+            call(r#"{"call": "set_engine", "engine": "ste"}"#);
+            call(r#"{"call": "ready"}"#);
+            let start = call(r#"{"call": "get_position"}"#)["ok"].clone();
+            call(r#"{"call": "plan_move", "direction": "n"}"#);
+            while call(r#"{"call": "is_moving"}"#)["ok"] == true {
+                call(r#"{"call": "wait_for_update"}"#);
+            }
+            start
+        });
+        let (stream, _) = listener.accept().unwrap();
+        let mut arena = Arena::new(ArenaConfig::default());
+        arena.add_remote("remote".to_string(), stream);
+        let result = arena.run().unwrap();
+        let start = bot.join().unwrap();
+
+        assert_eq!(result.ticks, 12);
+        assert!(result.script_errors.is_empty());
+        let Position(x, y) = result.units[0].position;
+        assert_eq!(
+            (x as i64 - 1, y as i64),
+            (start["x"].as_i64().unwrap(), start["y"].as_i64().unwrap())
+        );
+    }
+
+    #[test]
+    fn disqualified_mid_move() {
+        let mut arena = Arena::new(ArenaConfig {
+            max_ticks: 30,
+            spawns: vec![
+                Spawn {
+                    pos: Position(0, 0),
+                    team: None,
+                },
+                Spawn {
+                    pos: Position(0, 2),
+                    team: None,
+                },
+            ],
+            script: ScriptConfig {
+                max_instructions: Some(200_000),
+                ..ScriptConfig::default()
+            },
+            ..ArenaConfig::default()
+        });
+        arena
+            .add_script(
+                "spinner".to_string(),
+                r#"
+                    api:set_engine(utils:new_engine("ste"))
+                    api:init()
+                    api:plan_move("N")
+                    while true do end
+                "#
+                .to_string(),
+            )
+            .add_script("sitter".to_string(), SITTER.to_string());
+        let result = arena.run().unwrap();
+        assert_eq!(result.ticks, 30);
+        assert_eq!(result.script_errors.len(), 1);
+        assert!(result.update_errors.is_empty());
+        assert_eq!(result.units[0].position, Position(0, 0));
+    }
+
+    #[test]
+    fn script_error() {
+        let mut arena = Arena::new(ArenaConfig::default());
+        arena
+            .add_script("broken".to_string(), "api:init()".to_string())
+            .add_script("sitter".to_string(), SITTER.to_string());
+        let result = arena.run().unwrap();
+        assert_eq!(result.script_errors.len(), 1);
+        assert_eq!(result.script_errors[0].0, "broken");
+        assert_eq!(result.units.len(), 1);
+    }
+
+    #[test]
+    fn busy_loop_preempted() {
+        let mut arena = Arena::new(ArenaConfig {
+            max_ticks: 30,
+            turn_budget: Some(10_000),
+            ..ArenaConfig::default()
+        });
+        arena
+            .add_script(
+                "spinner".to_string(),
+                r#"
+                    api:set_engine(utils:new_engine("ste"))
+                    api:init()
+                    while true do end
+                "#
+                .to_string(),
+            )
+            .add_script(
+                "walker".to_string(),
+                r#"
+                    api:set_engine(utils:new_engine("ste"))
+                    api:init()
+                    local start = api:get_position()
+                    api:plan_move('N')
+                    while api:is_moving() do
+                        api:wait_for_update()
+                    end
+                    assert(api:get_position().x == start.x + 1)
+                    while true do
+                        api:wait_for_update()
+                    end
+                "#
+                .to_string(),
+            );
+        let result = arena.run().unwrap();
+        assert_eq!(result.ticks, 30);
+        assert!(result.script_errors.is_empty());
+        assert_eq!(result.survivors.len(), 2);
+    }
+
+    #[test]
+    fn no_room() {
+        let mut arena = Arena::new(ArenaConfig {
+            field_size: 1,
+            ..ArenaConfig::default()
+        });
+        arena
+            .add_script("a".to_string(), SITTER.to_string())
+            .add_script("b".to_string(), SITTER.to_string());
+        assert!(matches!(arena.run(), Err(KikanError::NoRoomForUnits(2))));
+    }
+
+    #[test]
+    fn walled_in() {
+        let mut map = Map::new(2, 2);
+        map.add_wall(Position(0, 0)).add_wall(Position(1, 1));
+        let mut arena = Arena::new(ArenaConfig {
+            max_ticks: 30,
+            map: Some(map),
+            ..ArenaConfig::default()
+        });
+        arena.add_script("a".to_string(), SITTER.to_string()).add_script(
+            "b".to_string(),
+            r#"
+                api:set_engine(utils:new_engine("ste"))
+                api:init()
+                local start = api:get_position()
+                api:plan_move('N')
+                while api:is_moving() do
+                    api:wait_for_update()
+                end
+                assert(api:crashed())
+                assert(api:get_position().x == start.x)
+                while true do
+                    api:wait_for_update()
+                end
+            "#
+            .to_string(),
+        );
+        let result = arena.run().unwrap();
+        assert!(result.script_errors.is_empty());
+        let mut starts: Vec<Position> = result.units.iter().map(|unit| unit.position).collect();
+        starts.sort();
+        assert_eq!(starts, vec![Position(0, 1), Position(1, 0)]);
+    }
+
+    #[test]
+    fn teams() {
+        let file = MapFile::parse("[teams]\nred = [0, 1]\nblue = [2]\n---\n1.2\n0#.").unwrap();
+        let mut arena = Arena::new(ArenaConfig {
+            max_ticks: 300,
+            map: Some(file.map),
+            spawns: file.spawns,
+            ..ArenaConfig::default()
+        });
+        arena
+            .add_script("a".to_string(), SITTER.to_string())
+            .add_script("b".to_string(), SITTER.to_string());
+        let result = arena.run().unwrap();
+        // nobody is left to fight
+        assert!(result.ticks < 5);
+        assert_eq!(result.winning_team(), Some("red"));
+        assert!(result.winner().is_none());
+        let a = result
+            .units
+            .iter()
+            .find(|unit| unit.name.as_deref() == Some("a"))
+            .unwrap();
+        assert_eq!(a.position, Position(0, 0));
+        assert_eq!(a.team.as_deref(), Some("red"));
+    }
+}
@@ -1,18 +1,25 @@
 use mlua::UserData;
+use serde::{Deserialize, Serialize};
 
 use crate::{
-    arsenal::{Commit, UnitAction, UnitMod, UnitPart, UnitScore, UnitStatus},
+    arsenal::{Commit, CommitState, Energy, PartState, UnitAction, UnitMod, UnitPart, UnitScore, UnitStatus},
     error::{KResult, KikanError},
     kikan::{Position, UnitId},
+    map::Terrain,
+    topology::Topology,
 };
 use std::{num::NonZeroUsize, str::FromStr};
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Move {
     N, // ↑
     S, // ↓
     W, // ←
     E, // →
+    NE,
+    NW,
+    SE,
+    SW,
 }
 
 impl FromStr for Move {
@@ -24,12 +31,77 @@ impl FromStr for Move {
             "S" | "s" => Self::S,
             "W" | "w" => Self::W,
             "E" | "e" => Self::E,
+            "NE" | "ne" => Self::NE,
+            "NW" | "nw" => Self::NW,
+            "SE" | "se" => Self::SE,
+            "SW" | "sw" => Self::SW,
             _ => return Err(()),
         })
     }
 }
 
-impl UnitAction for Move {}
+impl Move {
+    pub const ALL: [Move; 8] = [
+        Self::N,
+        Self::S,
+        Self::W,
+        Self::E,
+        Self::NE,
+        Self::NW,
+        Self::SE,
+        Self::SW,
+    ];
+
+    /// The cell one step away from `from`.
+    pub fn next(self, from: Position) -> Position {
+        let Position(x, y) = from;
+        match self {
+            Self::N => Position(x + 1, y),
+            Self::S => Position(x - 1, y),
+            Self::W => Position(x, y - 1),
+            Self::E => Position(x, y + 1),
+            Self::NE => Position(x + 1, y + 1),
+            Self::NW => Position(x + 1, y - 1),
+            Self::SE => Position(x - 1, y + 1),
+            Self::SW => Position(x - 1, y - 1),
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::N => "N",
+            Self::S => "S",
+            Self::W => "W",
+            Self::E => "E",
+            Self::NE => "NE",
+            Self::NW => "NW",
+            Self::SE => "SE",
+            Self::SW => "SW",
+        }
+    }
+
+    pub fn is_diagonal(self) -> bool {
+        matches!(self, Self::NE | Self::NW | Self::SE | Self::SW)
+    }
+}
+
+/// Where `plan_path` sends a unit.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Route {
+    Moves(Vec<Move>),
+    /// the cheapest way there for the engine of the unit
+    To(Position),
+}
+
+/// A move onto a cell of the given terrain.
+#[derive(Debug, Clone, Copy)]
+pub struct Step {
+    pub direction: Move,
+    pub terrain: Terrain,
+    pub topology: Topology,
+}
+
+impl UnitAction for Step {}
 
 pub struct MoveCommit {
     resolve_delay: NonZeroUsize,
@@ -38,7 +110,7 @@ pub struct MoveCommit {
 }
 
 impl MoveCommit {
-    fn new(resolve_delay: usize, next_move: Move) -> Self {
+    pub(crate) fn new(resolve_delay: usize, next_move: Move) -> Self {
         let resolve_delay = NonZeroUsize::new(resolve_delay).unwrap();
         Self {
             resolve_delay,
@@ -59,50 +131,252 @@ impl Commit for MoveCommit {
 
     fn take_commit(&self, kikan: &mut crate::kikan::Kikan) -> KResult<()> {
         let unit_id = self.unit_id.unwrap();
-        let Position(x, y) = kikan.get_unit_position(unit_id).ok_or(KikanError::GhostUnit)?;
-        let pos = match self.next_move {
-            Move::N => Position(x + 1, y),
-            Move::S => Position(x - 1, y),
-            Move::W => Position(x, y - 1),
-            Move::E => Position(x, y + 1),
-        };
+        if !kikan.is_unit_alive(unit_id)
+            || matches!(kikan.get_unit_by_id(unit_id)?.engine.status(), Ok(UnitStatus::Offline))
+        {
+            // wrecks and disqualified units keep their place
+            return Ok(());
+        }
+        let pos = kikan.get_unit_position(unit_id).ok_or(KikanError::GhostUnit)?;
+        let pos = kikan
+            .topology()
+            .neighbour(pos, self.next_move)
+            .ok_or(KikanError::InvalidMove)?;
         kikan.commit_move(unit_id, pos);
         let unit = kikan.get_unit_by_id(unit_id)?;
         unit.engine.action_done()
     }
+
+    fn save(&self) -> CommitState {
+        CommitState::Move {
+            unit: self.unit_id,
+            next_move: self.next_move,
+            delay: self.resolve_delay.get(),
+        }
+    }
+}
+
+/// Numbers of one kind of engine.
+pub struct EngineSpec {
+    /// how hard a unit with this engine hits in a crash, heavier pushes lighter
+    pub mass: u32,
+    /// added to the armor of the unit
+    pub armor: u32,
+    pub score: UnitScore,
+    /// ticks the engine is busy after its unit crashed
+    pub stall: usize,
+    /// per step
+    pub energy: Energy,
+    /// ticks to drive onto a cell of that terrain, `None` where the engine cannot go
+    pub delay: fn(Terrain) -> Option<usize>,
 }
 
-/// engine
-#[derive(Debug, Default, Clone, Copy)]
-pub struct STE {
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EngineType {
+    /// standard tracked engine
+    STE,
+    /// fast and cheap, stalls for a while after a crash
+    Light,
+    /// slow and armored, shrugs off crashes and rough ground
+    Heavy,
+    /// crosses water and mud, stalls long after a crash
+    Hover,
+}
+
+impl EngineType {
+    pub const ALL: [EngineType; 4] = [Self::STE, Self::Light, Self::Heavy, Self::Hover];
+
+    pub fn spec(self) -> EngineSpec {
+        match self {
+            Self::STE => EngineSpec {
+                mass: 20,
+                armor: 0,
+                score: 15,
+                stall: 0,
+                energy: 2,
+                delay: |terrain| match terrain {
+                    Terrain::Road => Some(6),
+                    Terrain::Open => Some(10),
+                    Terrain::Rough => Some(16),
+                    Terrain::Mud => Some(30),
+                    Terrain::Water => None,
+                },
+            },
+            Self::Light => EngineSpec {
+                mass: 10,
+                armor: 0,
+                score: 10,
+                stall: 10,
+                energy: 1,
+                delay: |terrain| match terrain {
+                    Terrain::Road => Some(3),
+                    Terrain::Open => Some(5),
+                    Terrain::Rough => Some(12),
+                    Terrain::Mud => Some(40),
+                    Terrain::Water => None,
+                },
+            },
+            Self::Heavy => EngineSpec {
+                mass: 40,
+                armor: 5,
+                score: 25,
+                stall: 0,
+                energy: 4,
+                delay: |terrain| match terrain {
+                    Terrain::Road => Some(12),
+                    Terrain::Open => Some(16),
+                    Terrain::Rough => Some(18),
+                    Terrain::Mud => Some(24),
+                    Terrain::Water => None,
+                },
+            },
+            Self::Hover => EngineSpec {
+                mass: 15,
+                armor: 0,
+                score: 20,
+                stall: 20,
+                energy: 3,
+                delay: |terrain| match terrain {
+                    Terrain::Road | Terrain::Open | Terrain::Mud | Terrain::Water => Some(8),
+                    Terrain::Rough => Some(20),
+                },
+            },
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::STE => "ste",
+            Self::Light => "light",
+            Self::Heavy => "heavy",
+            Self::Hover => "hover",
+        }
+    }
+
+    pub fn into_engine(self) -> Box<dyn UnitMod<Step> + Send> {
+        Box::new(Engine::new(self))
+    }
+
+    pub fn mass(self) -> u32 {
+        self.spec().mass
+    }
+
+    pub fn move_delay(self, terrain: Terrain) -> Option<usize> {
+        (self.spec().delay)(terrain)
+    }
+
+    /// Diagonal steps on squares cover more ground, they take 1.4 times as long.
+    pub fn step_delay(self, step: Step) -> Option<usize> {
+        let delay = self.move_delay(step.terrain)?;
+        Some(if step.topology.is_long(step.direction) {
+            delay * 7 / 5
+        } else {
+            delay
+        })
+    }
+
+    /// Diagonal steps on squares cost half as much again.
+    pub fn step_energy(self, step: Step) -> Energy {
+        let energy = self.spec().energy;
+        if step.topology.is_long(step.direction) {
+            energy + energy / 2
+        } else {
+            energy
+        }
+    }
+}
+
+impl UserData for EngineType {}
+
+impl FromStr for EngineType {
+    type Err = KikanError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "STE" | "ste" => Self::STE,
+            "light" => Self::Light,
+            "heavy" | "tracked" => Self::Heavy,
+            "hover" => Self::Hover,
+            _ => return Err(KikanError::NoSuchMod),
+        })
+    }
+}
+
+pub struct Engine {
+    engine_type: EngineType,
     now_on: Option<Move>,
+    /// ticks left before it runs again after a crash
+    stalled: usize,
     offline: bool,
 }
 
-impl UnitPart for STE {
+impl Engine {
+    pub fn new(engine_type: EngineType) -> Self {
+        Self {
+            engine_type,
+            now_on: None,
+            stalled: 0,
+            offline: false,
+        }
+    }
+
+    pub(crate) fn restore(engine_type: EngineType, now_on: Option<Move>, stalled: usize, offline: bool) -> Self {
+        Self {
+            engine_type,
+            now_on,
+            stalled,
+            offline,
+        }
+    }
+
+    pub fn engine_type(&self) -> EngineType {
+        self.engine_type
+    }
+}
+
+impl UnitPart for Engine {
     fn score(&self) -> UnitScore {
-        0
+        self.engine_type.spec().score
     }
 }
 
-impl UnitMod<Move> for STE {
+impl UnitMod<Step> for Engine {
     fn status(&self) -> KResult<UnitStatus> {
         if self.offline {
             Ok(UnitStatus::Offline)
-        } else if self.now_on.is_some() {
+        } else if self.now_on.is_some() || self.stalled > 0 {
             Ok(UnitStatus::Busy)
         } else {
             Ok(UnitStatus::Operational)
         }
     }
 
-    fn action(&mut self, action: Move) -> KResult<Box<dyn Commit>> {
+    fn action(&mut self, action: Step) -> KResult<Box<dyn Commit>> {
         self.status()?.operational_or_err()?;
-        self.now_on = Some(action);
-        let commit = MoveCommit::new(10, action);
+        let delay = self.engine_type.step_delay(action).ok_or(KikanError::BlockedCell)?;
+        self.now_on = Some(action.direction);
+        let commit = MoveCommit::new(delay, action.direction);
         Ok(Box::new(commit))
     }
 
+    fn name(&self) -> &'static str {
+        self.engine_type.as_str()
+    }
+
+    fn save(&self) -> PartState {
+        PartState::Engine {
+            engine: self.engine_type,
+            moving: self.now_on,
+            stalled: self.stalled,
+            offline: self.offline,
+        }
+    }
+
+    fn energy_cost(&self, action: &Step) -> Energy {
+        self.engine_type.step_energy(*action)
+    }
+
     fn action_done(&mut self) -> KResult<()> {
         self.status()?.online_or_err()?;
         self.now_on = None;
@@ -115,30 +389,71 @@ impl UnitMod<Move> for STE {
         self.offline = true;
         Ok(())
     }
-}
 
-#[derive(Clone, Copy, PartialEq, Eq, Debug)]
-pub enum EngineType {
-    STE,
+    fn tick(&mut self) {
+        self.stalled = self.stalled.saturating_sub(1);
+    }
+
+    fn jolt(&mut self) {
+        self.stalled = self.stalled.max(self.engine_type.spec().stall);
+    }
 }
 
-impl EngineType {
-    pub fn into_engine(self) -> Box<dyn UnitMod<Move> + Send> {
-        match self {
-            Self::STE => Box::new(STE::default()),
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn step(terrain: Terrain) -> Step {
+        Step {
+            direction: Move::N,
+            terrain,
+            topology: Topology::Square,
         }
     }
-}
 
-impl UserData for EngineType {}
+    #[test]
+    fn delays() {
+        let delay = |engine: EngineType, terrain| {
+            let mut engine = engine.into_engine();
+            engine.action(step(terrain)).map(|commit| commit.resolve_at().get())
+        };
+        assert_eq!(delay(EngineType::Light, Terrain::Open).unwrap(), 5);
+        assert_eq!(delay(EngineType::Heavy, Terrain::Mud).unwrap(), 24);
+        assert_eq!(delay(EngineType::Hover, Terrain::Water).unwrap(), 8);
+        assert!(matches!(
+            delay(EngineType::Heavy, Terrain::Water),
+            Err(KikanError::BlockedCell)
+        ));
 
-impl FromStr for EngineType {
-    type Err = KikanError;
+        let mut ste = EngineType::STE.into_engine();
+        let diagonal = Step {
+            direction: Move::SW,
+            terrain: Terrain::Open,
+            topology: Topology::Square,
+        };
+        assert_eq!(ste.energy_cost(&diagonal), 3);
+        assert_eq!(ste.action(diagonal).unwrap().resolve_at().get(), 14);
+        let hex = Step {
+            direction: Move::SE,
+            topology: Topology::Hex,
+            ..diagonal
+        };
+        assert_eq!(ste.energy_cost(&hex), 2);
+        assert_eq!(EngineType::STE.step_delay(hex), Some(10));
+    }
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        Ok(match s {
-            "STE" | "ste" => Self::STE,
-            _ => return Err(KikanError::NoSuchMod),
-        })
+    #[test]
+    fn stall() {
+        let mut light = Engine::new(EngineType::Light);
+        light.jolt();
+        assert!(matches!(light.action(step(Terrain::Open)), Err(KikanError::ModBusy)));
+        for _ in 0..EngineType::Light.spec().stall {
+            light.tick();
+        }
+        assert!(light.action(step(Terrain::Open)).is_ok());
+
+        let mut heavy = Engine::new(EngineType::Heavy);
+        heavy.jolt();
+        assert!(heavy.status().unwrap().is_operation());
     }
 }
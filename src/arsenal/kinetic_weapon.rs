@@ -1,16 +1,19 @@
 use crate::{
-    error::KResult,
+    error::{KResult, KikanError},
     kikan::{Kikan, Position, UnitId},
+    topology::Topology,
 };
+use serde::{Deserialize, Serialize};
 use std::num::NonZeroUsize;
 
-use super::{Commit, UnitAction};
+use super::{Commit, CommitState, Energy, PartState, UnitAction, UnitMod, UnitPart, UnitScore, UnitStatus};
 
 pub struct KineticWeaponCommit {
     pub(crate) delay: Box<dyn Fn(usize) -> usize + Sync + Send>,
     pub distance: usize,
     pub target: Position,
     pub damage: u32,
+    pub shooter: Option<UnitId>,
 }
 
 impl Commit for KineticWeaponCommit {
@@ -18,12 +21,266 @@ impl Commit for KineticWeaponCommit {
         NonZeroUsize::new((self.delay)(self.distance)).unwrap_or_else(|| unsafe { NonZeroUsize::new_unchecked(1) })
     }
 
-    fn take_commit(&self, _kikan: &mut Kikan) -> KResult<()> {
+    fn take_commit(&self, kikan: &mut Kikan) -> KResult<()> {
+        if let Some(unit_id) = kikan.get_unit_at(self.target) {
+            kikan.damage_unit(unit_id, self.damage, self.shooter)?;
+        }
         Ok(())
     }
 
-    /// this mod will use no unit id.
-    fn fill_unit_id(&mut self, _: UnitId) {}
+    /// the shooter, hits are credited to it.
+    fn fill_unit_id(&mut self, id: UnitId) {
+        self.shooter = Some(id);
+    }
+
+    fn save(&self) -> CommitState {
+        CommitState::Shot {
+            shooter: self.shooter,
+            target: self.target,
+            damage: self.damage,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Aim {
+    pub from: Position,
+    pub target: Position,
+    pub topology: Topology,
+}
+
+impl UnitAction for Aim {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KineticWeaponType {
+    Cannon,
+    Railgun,
+    Autocannon,
+}
+
+/// Numbers of one kind of kinetic weapon.
+pub struct KineticWeaponSpec {
+    pub range: usize,
+    pub damage: u32,
+    /// ticks before the weapon can fire again
+    pub reload: usize,
+    pub ammo: u32,
+    pub score: UnitScore,
+    /// per shot
+    pub energy: Energy,
+    /// distance to ticks of flight
+    pub delay: fn(usize) -> usize,
+}
+
+impl KineticWeaponType {
+    pub const ALL: [KineticWeaponType; 3] = [Self::Cannon, Self::Railgun, Self::Autocannon];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Cannon => "cannon",
+            Self::Railgun => "railgun",
+            Self::Autocannon => "autocannon",
+        }
+    }
+
+    pub fn spec(self) -> KineticWeaponSpec {
+        match self {
+            Self::Cannon => KineticWeaponSpec {
+                range: 6,
+                damage: 40,
+                reload: 20,
+                ammo: 10,
+                score: 30,
+                energy: 10,
+                delay: |distance| 1 + distance,
+            },
+            Self::Railgun => KineticWeaponSpec {
+                range: 12,
+                damage: 70,
+                reload: 60,
+                ammo: 4,
+                score: 50,
+                energy: 40,
+                delay: |_| 1,
+            },
+            Self::Autocannon => KineticWeaponSpec {
+                range: 4,
+                damage: 10,
+                reload: 4,
+                ammo: 40,
+                score: 20,
+                energy: 3,
+                delay: |distance| 1 + distance / 2,
+            },
+        }
+    }
+}
+
+pub struct KineticWeapon {
+    weapon_type: KineticWeaponType,
+    ammo: u32,
+    cooldown: usize,
+    offline: bool,
+}
+
+impl KineticWeapon {
+    pub fn new(weapon_type: KineticWeaponType) -> Self {
+        Self {
+            weapon_type,
+            ammo: weapon_type.spec().ammo,
+            cooldown: 0,
+            offline: false,
+        }
+    }
+
+    pub(crate) fn restore(weapon_type: KineticWeaponType, ammo: u32, cooldown: usize, offline: bool) -> Self {
+        Self {
+            weapon_type,
+            ammo,
+            cooldown,
+            offline,
+        }
+    }
+
+    pub fn weapon_type(&self) -> KineticWeaponType {
+        self.weapon_type
+    }
+
+    pub fn ammo(&self) -> u32 {
+        self.ammo
+    }
+}
+
+impl UnitPart for KineticWeapon {
+    fn score(&self) -> UnitScore {
+        self.weapon_type.spec().score
+    }
 }
 
-impl UnitAction for Position {}
+impl UnitMod<Aim> for KineticWeapon {
+    fn status(&self) -> KResult<UnitStatus> {
+        if self.offline {
+            Ok(UnitStatus::Offline)
+        } else if self.cooldown > 0 {
+            Ok(UnitStatus::Busy)
+        } else {
+            Ok(UnitStatus::Operational)
+        }
+    }
+
+    fn action(&mut self, action: Aim) -> KResult<Box<dyn Commit>> {
+        self.status()?.operational_or_err()?;
+        let spec = self.weapon_type.spec();
+        let distance = action.topology.distance(action.from, action.target);
+        if distance > spec.range {
+            return Err(KikanError::OutOfRange);
+        }
+        if self.ammo == 0 {
+            return Err(KikanError::OutOfAmmo);
+        }
+        self.ammo -= 1;
+        self.cooldown = spec.reload;
+        let commit = KineticWeaponCommit {
+            delay: Box::new(spec.delay),
+            distance,
+            target: action.target,
+            damage: spec.damage,
+            shooter: None,
+        };
+        Ok(Box::new(commit))
+    }
+
+    fn name(&self) -> &'static str {
+        self.weapon_type.as_str()
+    }
+
+    fn save(&self) -> PartState {
+        PartState::KineticWeapon {
+            weapon: self.weapon_type,
+            ammo: self.ammo,
+            cooldown: self.cooldown,
+            offline: self.offline,
+        }
+    }
+
+    fn energy_cost(&self, _action: &Aim) -> Energy {
+        self.weapon_type.spec().energy
+    }
+
+    fn action_done(&mut self) -> KResult<()> {
+        self.status()?.online_or_err()?;
+        self.cooldown = 0;
+        Ok(())
+    }
+
+    fn mark_as_offline(&mut self) -> KResult<()> {
+        self.status()?.online_or_err()?;
+        self.cooldown = 0;
+        self.offline = true;
+        Ok(())
+    }
+
+    fn tick(&mut self) {
+        self.cooldown = self.cooldown.saturating_sub(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn aim(x: i32, y: i32) -> Aim {
+        Aim {
+            from: Position(0, 0),
+            target: Position(x, y),
+            topology: Topology::Square,
+        }
+    }
+
+    #[test]
+    fn out_of_range() {
+        let mut cannon = KineticWeapon::new(KineticWeaponType::Cannon);
+        assert!(matches!(cannon.action(aim(4, 3)), Err(KikanError::OutOfRange)));
+        assert_eq!(cannon.ammo(), KineticWeaponType::Cannon.spec().ammo);
+        assert!(cannon.action(aim(3, 3)).is_ok());
+    }
+
+    #[test]
+    fn reload() {
+        let mut autocannon = KineticWeapon::new(KineticWeaponType::Autocannon);
+        let commit = autocannon.action(aim(0, 4)).unwrap();
+        assert_eq!(commit.resolve_at().get(), 3);
+        assert!(autocannon.status().unwrap().is_busy());
+        assert!(matches!(autocannon.action(aim(0, 4)), Err(KikanError::ModBusy)));
+        for _ in 0..KineticWeaponType::Autocannon.spec().reload {
+            autocannon.tick();
+        }
+        assert!(autocannon.status().unwrap().is_operation());
+        assert!(autocannon.action(aim(0, 4)).is_ok());
+    }
+
+    #[test]
+    fn out_of_ammo() {
+        let mut railgun = KineticWeapon::new(KineticWeaponType::Railgun);
+        for _ in 0..KineticWeaponType::Railgun.spec().ammo {
+            railgun.action(aim(1, 0)).unwrap();
+            railgun.action_done().unwrap();
+        }
+        assert!(matches!(railgun.action(aim(1, 0)), Err(KikanError::OutOfAmmo)));
+    }
+
+    #[test]
+    fn energy() {
+        let mut railgun = KineticWeapon::new(KineticWeaponType::Railgun);
+        let mut energy = 50;
+        railgun.powered_action(aim(1, 0), &mut energy).unwrap();
+        assert_eq!(energy, 10);
+        railgun.action_done().unwrap();
+        assert!(matches!(
+            railgun.powered_action(aim(1, 0), &mut energy),
+            Err(KikanError::OutOfEnergy)
+        ));
+        assert_eq!(railgun.ammo(), KineticWeaponType::Railgun.spec().ammo - 1);
+    }
+}
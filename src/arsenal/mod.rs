@@ -3,13 +3,19 @@ use mlua::UserData;
 use crate::{
     error::{KResult, KikanError},
     kikan::{Kikan, Position, UnitId},
+    map::Terrain,
+    topology::Topology,
 };
-use std::{collections::HashMap, num::NonZeroUsize};
+use engine::{Engine, EngineType, Move, MoveCommit, Step};
+use kinetic_weapon::{Aim, KineticWeapon, KineticWeaponCommit, KineticWeaponType};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, num::NonZeroUsize, str::FromStr};
 
 pub mod engine;
 pub mod kinetic_weapon;
 
 pub type UnitScore = u32;
+pub type Energy = u32;
 
 pub trait UnitPart {
     fn score(&self) -> UnitScore;
@@ -29,7 +35,7 @@ where
     T: UnitPart,
 {
     fn score(&self) -> UnitScore {
-        self.iter().map(|(_, unit)| unit.score()).sum()
+        self.values().map(|unit| unit.score()).sum()
     }
 }
 
@@ -76,9 +82,36 @@ pub trait UnitMod<A: UnitAction>: UnitPart + Send {
 
     fn action(&mut self, action: A) -> KResult<Box<dyn Commit>>;
 
+    /// The kind of part, as scripts name it when building a unit.
+    fn name(&self) -> &'static str;
+
+    fn save(&self) -> PartState;
+
+    /// Energy the unit pays for `action`.
+    fn energy_cost(&self, _action: &A) -> Energy {
+        0
+    }
+
+    /// Runs `action` if `energy` covers its cost, and takes the cost from it.
+    fn powered_action(&mut self, action: A, energy: &mut Energy) -> KResult<Box<dyn Commit>> {
+        let cost = self.energy_cost(&action);
+        if cost > *energy {
+            return Err(KikanError::OutOfEnergy);
+        }
+        let commit = self.action(action)?;
+        *energy -= cost;
+        Ok(commit)
+    }
+
     fn action_done(&mut self) -> KResult<()>;
 
     fn mark_as_offline(&mut self) -> KResult<()>;
+
+    /// Called by the world once every tick.
+    fn tick(&mut self) {}
+
+    /// The unit crashed into something.
+    fn jolt(&mut self) {}
 }
 
 pub trait UnitAction: Clone + Send + Sync {}
@@ -87,6 +120,117 @@ pub trait Commit: Send + Sync {
     fn resolve_at(&self) -> NonZeroUsize;
     fn fill_unit_id(&mut self, id: UnitId);
     fn take_commit(&self, kikan: &mut Kikan) -> KResult<()>;
+    fn save(&self) -> CommitState;
+}
+
+/// A unit part as it is right now, for snapshots.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum PartState {
+    Engine {
+        engine: EngineType,
+        moving: Option<Move>,
+        stalled: usize,
+        offline: bool,
+    },
+    KineticWeapon {
+        weapon: KineticWeaponType,
+        ammo: u32,
+        cooldown: usize,
+        offline: bool,
+    },
+}
+
+impl PartState {
+    pub fn into_engine(self) -> KResult<(EngineType, Box<dyn UnitMod<Step> + Send>)> {
+        match self {
+            Self::Engine {
+                engine,
+                moving,
+                stalled,
+                offline,
+            } => Ok((engine, Box::new(Engine::restore(engine, moving, stalled, offline)))),
+            _ => Err(KikanError::BadSnapshot("a mod in place of the engine".to_string())),
+        }
+    }
+
+    pub fn into_mod(self) -> KResult<UnitModContainter> {
+        match self {
+            Self::KineticWeapon {
+                weapon,
+                ammo,
+                cooldown,
+                offline,
+            } => Ok(UnitModContainter::KineticWeapon(Box::new(KineticWeapon::restore(
+                weapon, ammo, cooldown, offline,
+            )))),
+            Self::Engine { .. } => Err(KikanError::BadSnapshot("an engine in place of a mod".to_string())),
+        }
+    }
+}
+
+/// A queued commit, for snapshots.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum CommitState {
+    Move {
+        unit: Option<UnitId>,
+        next_move: Move,
+        delay: usize,
+    },
+    Shot {
+        shooter: Option<UnitId>,
+        target: Position,
+        damage: u32,
+    },
+    Nothing,
+}
+
+impl CommitState {
+    /// Goes back into the slot of the queue it was saved from, so its delay is not needed again.
+    pub fn restore(self) -> KResult<Box<dyn Commit>> {
+        Ok(match self {
+            Self::Move { unit, next_move, delay } => {
+                if delay == 0 {
+                    return Err(KikanError::BadSnapshot("move without delay".to_string()));
+                }
+                let mut commit = MoveCommit::new(delay, next_move);
+                if let Some(unit) = unit {
+                    commit.fill_unit_id(unit);
+                }
+                Box::new(commit)
+            }
+            Self::Shot {
+                shooter,
+                target,
+                damage,
+            } => Box::new(KineticWeaponCommit {
+                delay: Box::new(|_| 1),
+                distance: 0,
+                target,
+                damage,
+                shooter,
+            }),
+            Self::Nothing => Box::new(()),
+        })
+    }
+}
+
+/// Ticks the slowest commit waits, a slow diagonal step or a shot at full range.
+pub fn longest_delay() -> usize {
+    let steps = EngineType::ALL
+        .into_iter()
+        .flat_map(|engine| {
+            Terrain::ALL
+                .into_iter()
+                .filter_map(move |terrain| engine.move_delay(terrain))
+        })
+        .map(|delay| delay * 7 / 5);
+    let shots = KineticWeaponType::ALL.into_iter().map(|weapon| {
+        let spec = weapon.spec();
+        (spec.delay)(spec.range)
+    });
+    steps.chain(shots).max().unwrap_or(1)
 }
 
 impl Commit for () {
@@ -101,10 +245,14 @@ impl Commit for () {
     }
 
     fn fill_unit_id(&mut self, _: UnitId) {}
+
+    fn save(&self) -> CommitState {
+        CommitState::Nothing
+    }
 }
 
 pub enum UnitModContainter {
-    KineticWeapon(Box<dyn UnitMod<Position>>),
+    KineticWeapon(Box<dyn UnitMod<Aim>>),
 }
 
 impl UnitModContainter {
@@ -114,18 +262,80 @@ impl UnitModContainter {
         }
     }
 
-    pub fn take_action(&mut self, action: UnitActionContainer) -> KResult<Box<dyn Commit>> {
+    /// Parses back into the same [`ModType`].
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::KineticWeapon(umod) => umod.name(),
+        }
+    }
+
+    pub fn mark_as_offline(&mut self) -> KResult<()> {
+        match self {
+            Self::KineticWeapon(umod) => umod.mark_as_offline(),
+        }
+    }
+
+    pub fn tick(&mut self) {
+        match self {
+            Self::KineticWeapon(umod) => umod.tick(),
+        }
+    }
+
+    pub fn save(&self) -> PartState {
+        match self {
+            Self::KineticWeapon(umod) => umod.save(),
+        }
+    }
+
+    /// `from` is where the unit carrying this mod stands, the cost is taken from `energy`.
+    pub fn take_action(
+        &mut self,
+        from: Position,
+        topology: Topology,
+        action: UnitActionContainer,
+        energy: &mut Energy,
+    ) -> KResult<Box<dyn Commit>> {
         match self {
             Self::KineticWeapon(umod) => {
                 umod.status()?.operational_or_err()?;
                 match action {
-                    UnitActionContainer::Pos(action) => umod.action(action),
+                    UnitActionContainer::Pos(target) => umod.powered_action(Aim { from, target, topology }, energy),
                 }
             }
         }
     }
 }
 
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ModType {
+    KineticWeapon(KineticWeaponType),
+}
+
+impl ModType {
+    pub fn into_mod(self) -> UnitModContainter {
+        match self {
+            Self::KineticWeapon(weapon_type) => {
+                UnitModContainter::KineticWeapon(Box::new(KineticWeapon::new(weapon_type)))
+            }
+        }
+    }
+}
+
+impl UserData for ModType {}
+
+impl FromStr for ModType {
+    type Err = KikanError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "kinetic" | "cannon" => Self::KineticWeapon(KineticWeaponType::Cannon),
+            "railgun" => Self::KineticWeapon(KineticWeaponType::Railgun),
+            "autocannon" => Self::KineticWeapon(KineticWeaponType::Autocannon),
+            _ => return Err(KikanError::NoSuchMod),
+        })
+    }
+}
+
 impl UnitPart for UnitModContainter {
     fn score(&self) -> UnitScore {
         match self {
@@ -1,10 +1,28 @@
 use mlua::Error as LuaError;
+use std::fmt;
 use thiserror::Error;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScriptLimit {
+    Instructions,
+    Memory,
+}
+
+impl fmt::Display for ScriptLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Instructions => write!(f, "instruction"),
+            Self::Memory => write!(f, "memory"),
+        }
+    }
+}
+
 #[derive(Error, Debug)]
 pub enum KikanError {
     #[error("Lua error: {0}")]
     LuaError(#[from] LuaError),
+    #[error("IO error: {0}")]
+    IoError(#[from] std::io::Error),
     #[error("One unit can only init once")]
     AlreadyInited,
     #[error("Unit not been inited")]
@@ -13,6 +31,14 @@ pub enum KikanError {
     GhostUnit,
     #[error("There is already a unit")]
     AlreadyUnitHere,
+    #[error("This cell is blocked")]
+    BlockedCell,
+    #[error("No way to get there")]
+    NoPath,
+    #[error("No such move on this grid")]
+    InvalidMove,
+    #[error("No topology called {0}")]
+    NoSuchTopology(String),
     #[error("This mod is busy")]
     ModBusy,
     #[error("This mod is offline")]
@@ -25,6 +51,52 @@ pub enum KikanError {
     WrongUnitArgs(String),
     #[error("No such mod exists")]
     NoSuchMod,
+    #[error("No terrain called {0}")]
+    NoSuchTerrain(String),
+    #[error("Malformed map: {0}")]
+    MapSyntax(String),
+    #[error("Map grid is {2}x{3} but its header says {0}x{1}")]
+    MapSizeMismatch(u32, u32, u32, u32),
+    #[error("Unknown map cell {0:?} at ({1}, {2})")]
+    BadMapCell(char, i32, i32),
+    #[error("Spawn {0} is not on a free cell")]
+    BadSpawn(usize),
+    #[error("Spawn {0} is given twice")]
+    DuplicateSpawn(usize),
+    #[error("Team {0} refers to missing spawn {1}")]
+    UnknownSpawn(String, usize),
+    #[error("Not enough energy")]
+    OutOfEnergy,
+    #[error("Target out of range")]
+    OutOfRange,
+    #[error("Out of ammo")]
+    OutOfAmmo,
+    #[error("Match is over")]
+    MatchOver,
+    #[error("No room for `{0}` units")]
+    NoRoomForUnits(usize),
+    #[error("Unit costs {0} points, the budget is {1}")]
+    OverBudget(u32, u32),
+    #[error("Script exceeded its {0} limit")]
+    LimitExceeded(ScriptLimit),
+    #[error("No such library `{0}`")]
+    NoSuchLibrary(String),
+    #[error("Recording has to start before the first unit is placed")]
+    RecordingLate,
+    #[error("Replay format version {0} is not supported")]
+    ReplayVersion(u64),
+    #[error("Malformed replay: {0}")]
+    ReplaySyntax(String),
+    #[error("Replay went another way at tick {0}: {1}")]
+    ReplayDiverged(u64, String),
+    #[error("Snapshot format version {0} is not supported")]
+    SnapshotVersion(u64),
+    #[error("Malformed snapshot: {0}")]
+    BadSnapshot(String),
+    #[error("Malformed call: {0}")]
+    RemoteSyntax(String),
+    #[error("Bot was silent or stopped listening for too long")]
+    RemoteTimeout,
 }
 
 impl From<KikanError> for LuaError {
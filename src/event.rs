@@ -0,0 +1,90 @@
+use crate::kikan::{Position, UnitId};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Something that happened in the world.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Event {
+    /// `unit` moved from `from` to `to`, on its own or pushed.
+    Moved {
+        unit: UnitId,
+        from: Position,
+        to: Position,
+    },
+    /// `unit` could not move to `at`, `with` was in the way.
+    Collision {
+        unit: UnitId,
+        with: UnitId,
+        at: Position,
+    },
+    /// `unit` could not move to `at`, a wall or the edge of the map was in the way.
+    WallCollision {
+        unit: UnitId,
+        at: Position,
+    },
+    /// `unit` drove into `with` and pushed it on to `to`.
+    Pushed {
+        unit: UnitId,
+        with: UnitId,
+        to: Position,
+    },
+    /// `unit` stopped following its path at `at`.
+    PathInterrupted {
+        unit: UnitId,
+        at: Position,
+    },
+    /// `unit` fired its mod `weapon` at `target`.
+    ShotFired {
+        unit: UnitId,
+        weapon: String,
+        target: Position,
+    },
+    /// `unit` lost `damage` health, `by` is the other side of a shot or crash.
+    Hit {
+        unit: UnitId,
+        by: Option<UnitId>,
+        damage: u32,
+    },
+    /// `unit` lost its last health, `by` dealt the final hit.
+    Destroyed {
+        unit: UnitId,
+        by: Option<UnitId>,
+    },
+    /// `part` of `unit` went offline, `"engine"` for its engine.
+    ModOffline {
+        unit: UnitId,
+        part: String,
+    },
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let at = |pos: &Position| format!("({}, {})", pos.0, pos.1);
+        let by = |by: &Option<UnitId>| by.map(|id| format!(" by #{}", id)).unwrap_or_default();
+        match self {
+            Self::Moved { unit, from, to } => write!(f, "#{} moved {} -> {}", unit, at(from), at(to)),
+            Self::Collision { unit, with, at: pos } => write!(f, "#{} ran into #{} at {}", unit, with, at(pos)),
+            Self::WallCollision { unit, at: pos } => write!(f, "#{} hit a wall at {}", unit, at(pos)),
+            Self::Pushed { unit, with, to } => write!(f, "#{} pushed #{} to {}", unit, with, at(to)),
+            Self::PathInterrupted { unit, at: pos } => write!(f, "#{} stopped its path at {}", unit, at(pos)),
+            Self::ShotFired { unit, weapon, target } => write!(f, "#{} fired {} at {}", unit, weapon, at(target)),
+            Self::Hit {
+                unit,
+                by: source,
+                damage,
+            } => write!(f, "#{} lost {} health{}", unit, damage, by(source)),
+            Self::Destroyed { unit, by: source } => write!(f, "#{} destroyed{}", unit, by(source)),
+            Self::ModOffline { unit, part } => write!(f, "#{} {} offline", unit, part),
+        }
+    }
+}
+
+/// Broadcast after every update.
+/// Actions taken between two updates are reported with the later one.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TickEvent {
+    /// number of updates so far, the first one is 1
+    pub tick: u64,
+    pub events: Vec<Event>,
+}
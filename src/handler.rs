@@ -1,9 +1,16 @@
 use crate::{
-    arsenal::{engine::EngineType, UnitActionContainer},
+    arsenal::{
+        engine::{EngineType, Route},
+        Energy, ModType, UnitActionContainer, UnitPart,
+    },
     error::{KResult, KikanError},
+    event::Event,
     kikan::{Kikan, Move, Position, Unit, UnitId, UnitOrigin},
+    map::Terrain,
+    scheduler::Turn,
+    topology::Topology,
 };
-use mlua::{Error as LuaError, UserData};
+use mlua::{Error as LuaError, LuaSerdeExt, SerializeOptions, UserData, Value};
 use std::{
     mem,
     sync::{Arc, Mutex},
@@ -12,14 +19,33 @@ use std::{
 pub trait UnitHandler: Sized {
     // store id to handler
     fn set_engine(&mut self, engine: EngineType) -> KResult<()>;
+    fn add_mod(&mut self, mod_type: ModType, mod_id: String) -> KResult<()>;
     fn ready(&mut self) -> KResult<()>;
     fn get_position(&self) -> KResult<Position>;
     fn plan_move(&self, next_move: Move) -> KResult<()>;
+    fn plan_path(&self, route: Route) -> KResult<()>;
+    /// the last path was cut short, resets on read
+    fn path_interrupted(&self) -> KResult<bool>;
     fn is_moving(&self) -> KResult<bool>;
+    /// Whether the unit hit something since the last call.
+    fn crashed(&self) -> KResult<bool>;
+    fn terrain(&self, pos: Position) -> KResult<Terrain>;
+    fn topology(&self) -> KResult<Topology>;
+    fn energy(&self) -> KResult<Energy>;
+    /// what happened in the last update
+    fn last_events(&self) -> KResult<Vec<Event>>;
+    /// points left for parts, negative when over budget, `None` without a budget
+    fn budget_remaining(&self) -> KResult<Option<i64>>;
     fn package(self) -> Handler<Self> {
         Handler(self)
     }
-    fn wait_for_update(&self);
+    fn wait_for_update(&self) -> KResult<()>;
+    /// Called while the script runs, with the number of lua instructions since the last call.
+    fn spend_instructions(&self, _instructions: u32) -> KResult<()> {
+        Ok(())
+    }
+    /// Take the unit out of the match, its parts go offline.
+    fn disqualify(&self) -> KResult<()>;
     fn mod_action(&self, mod_id: String, action: UnitActionContainer) -> KResult<()>;
 }
 
@@ -52,26 +78,44 @@ pub struct LocalHandle {
     unit_id: Option<UnitId>,
     kikan: Arc<Mutex<Kikan>>,
     state: LocalHandlerState,
+    turn: Option<Turn>,
 }
 
 impl LocalHandle {
     pub fn new(kikan: Arc<Mutex<Kikan>>) -> Self {
+        Self::with_origin(kikan, Unit::builder())
+    }
+
+    /// Start from a prepared unit, scripts can still change it before `ready`.
+    pub fn with_origin(kikan: Arc<Mutex<Kikan>>, origin: UnitOrigin) -> Self {
         Self {
             kikan,
             unit_id: None,
-            state: LocalHandlerState::NotReady(Unit::builder()),
+            state: LocalHandlerState::NotReady(origin),
+            turn: None,
         }
     }
+
+    /// Wait for turns instead of world updates, see [`Scheduler`](crate::scheduler::Scheduler).
+    pub fn with_turn(mut self, turn: Turn) -> Self {
+        self.turn = Some(turn);
+        self
+    }
 }
 
 impl UnitHandler for LocalHandle {
     fn set_engine(&mut self, engine: EngineType) -> KResult<()> {
-        match engine {
-            EngineType::STE => {
-                let unit = self.state.get_unit_mut_ref()?;
-                unit.set_engine(engine);
-            }
+        let unit = self.state.get_unit_mut_ref()?;
+        unit.set_engine(engine);
+        Ok(())
+    }
+
+    fn add_mod(&mut self, mod_type: ModType, mod_id: String) -> KResult<()> {
+        let unit = self.state.get_unit_mut_ref()?;
+        if unit.mods.contains_key(&mod_id) {
+            return Err(KikanError::WrongUnitArgs(format!("mod id `{}` already in use", mod_id)));
         }
+        unit.add_mods(mod_type.into_mod(), mod_id);
         Ok(())
     }
 
@@ -79,11 +123,19 @@ impl UnitHandler for LocalHandle {
         if self.state.is_ready() || self.unit_id.is_some() {
             return Err(KikanError::AlreadyInited);
         }
+        // over budget units may still change their parts
+        self.kikan
+            .lock()
+            .unwrap()
+            .check_budget(self.state.get_unit_mut_ref()?)?;
         let mut state = LocalHandlerState::Ready;
         mem::swap(&mut state, &mut self.state);
         let unit = state.get_unit()?;
         let mut kikan = self.kikan.lock().unwrap();
-        let pos = kikan.gen_start_pos();
+        let pos = match unit.start {
+            Some(pos) => pos,
+            None => kikan.gen_start_pos()?,
+        };
         let id = kikan.add_unit(pos, unit)?;
         self.unit_id = Some(id);
         Ok(())
@@ -111,6 +163,24 @@ impl UnitHandler for LocalHandle {
         self.kikan.lock().unwrap().plan_unit_move(id, next_move)
     }
 
+    fn plan_path(&self, route: Route) -> KResult<()> {
+        let id = if let Some(id) = self.unit_id {
+            id
+        } else {
+            return Err(KikanError::Uninited);
+        };
+        self.kikan.lock().unwrap().plan_unit_path(id, route)
+    }
+
+    fn path_interrupted(&self) -> KResult<bool> {
+        let id = if let Some(id) = self.unit_id {
+            id
+        } else {
+            return Err(KikanError::Uninited);
+        };
+        self.kikan.lock().unwrap().take_unit_path_interrupted(id)
+    }
+
     fn is_moving(&self) -> KResult<bool> {
         let id = if let Some(id) = self.unit_id {
             id
@@ -120,9 +190,76 @@ impl UnitHandler for LocalHandle {
         self.kikan.lock().unwrap().is_unit_moving(id)
     }
 
-    fn wait_for_update(&self) {
+    fn crashed(&self) -> KResult<bool> {
+        let id = if let Some(id) = self.unit_id {
+            id
+        } else {
+            return Err(KikanError::Uninited);
+        };
+        self.kikan.lock().unwrap().take_unit_crash(id)
+    }
+
+    fn terrain(&self, pos: Position) -> KResult<Terrain> {
+        Ok(self.kikan.lock().unwrap().terrain_at(pos))
+    }
+
+    fn topology(&self) -> KResult<Topology> {
+        Ok(self.kikan.lock().unwrap().topology())
+    }
+
+    fn energy(&self) -> KResult<Energy> {
+        let id = if let Some(id) = self.unit_id {
+            id
+        } else {
+            return Err(KikanError::Uninited);
+        };
+        self.kikan
+            .lock()
+            .unwrap()
+            .get_unit_energy(id)
+            .ok_or(KikanError::GhostUnit)
+    }
+
+    fn last_events(&self) -> KResult<Vec<Event>> {
+        Ok(self.kikan.lock().unwrap().last_events().to_vec())
+    }
+
+    fn budget_remaining(&self) -> KResult<Option<i64>> {
+        let kikan = self.kikan.lock().unwrap();
+        let budget = match kikan.budget() {
+            Some(budget) => budget,
+            None => return Ok(None),
+        };
+        let spent = match (&self.state, self.unit_id) {
+            (LocalHandlerState::NotReady(origin), _) => origin.score(),
+            (LocalHandlerState::Ready, Some(id)) => kikan.get_unit(id).ok_or(KikanError::GhostUnit)?.score(),
+            (LocalHandlerState::Ready, None) => return Err(KikanError::GhostUnit),
+        };
+        Ok(Some(i64::from(budget) - i64::from(spent)))
+    }
+
+    fn wait_for_update(&self) -> KResult<()> {
+        if let Some(turn) = &self.turn {
+            return turn.end();
+        }
         let mut reader = { self.kikan.lock().unwrap().wait_for_update() };
-        reader.recv().ok();
+        reader.recv();
+        Ok(())
+    }
+
+    fn spend_instructions(&self, instructions: u32) -> KResult<()> {
+        match &self.turn {
+            Some(turn) => turn.spend(instructions),
+            None => Ok(()),
+        }
+    }
+
+    fn disqualify(&self) -> KResult<()> {
+        match self.unit_id {
+            Some(id) => self.kikan.lock().unwrap().disqualify_unit(id),
+            // nothing is on the field yet
+            None => Ok(()),
+        }
     }
 
     fn mod_action(&self, mod_id: String, action: UnitActionContainer) -> KResult<()> {
@@ -155,17 +292,63 @@ where
             Ok(this.0.plan_move(next_move)?)
         });
 
+        methods.add_method("plan_path", |_, this, route: Value| {
+            let route = match route {
+                Value::Table(moves) => Route::Moves(
+                    moves
+                        .sequence_values::<String>()
+                        .map(|next_move| {
+                            next_move?
+                                .parse()
+                                .map_err(|_| LuaError::RuntimeError("Invalid arg".to_string()))
+                        })
+                        .collect::<Result<_, _>>()?,
+                ),
+                Value::UserData(pos) => Route::To(*pos.borrow::<Position>()?),
+                _ => return Err(LuaError::RuntimeError("Invalid arg".to_string())),
+            };
+            Ok(this.0.plan_path(route)?)
+        });
+
+        methods.add_method("path_interrupted", |_, this, _: ()| Ok(this.0.path_interrupted()?));
+
         methods.add_method("get_position", |_, this, _: ()| Ok(this.0.get_position()?));
 
         methods.add_method("is_moving", |_, this, _: ()| Ok(this.0.is_moving()?));
 
-        methods.add_method("wait_for_update", |_, this, _: ()| {
-            this.0.wait_for_update();
-            Ok(())
+        methods.add_method("crashed", |_, this, _: ()| Ok(this.0.crashed()?));
+
+        methods.add_method("terrain", |_, this, pos: Position| Ok(this.0.terrain(pos)?.as_str()));
+
+        methods.add_method("topology", |_, this, _: ()| Ok(this.0.topology()?.as_str()));
+
+        methods.add_method("moves", |_, this, _: ()| {
+            Ok(this
+                .0
+                .topology()?
+                .moves()
+                .iter()
+                .map(|m| m.as_str())
+                .collect::<Vec<_>>())
         });
 
-        methods.add_method("mod_on", |_, this, (mod_id, action): (String, UnitActionContainer)| {
-            this.0.mod_action(mod_id, action)?;
+        methods.add_method("distance", |_, this, (from, to): (Position, Position)| {
+            Ok(this.0.topology()?.distance(from, to))
+        });
+
+        methods.add_method("energy", |_, this, _: ()| Ok(this.0.energy()?));
+
+        methods.add_method("last_events", |lua, this, _: ()| {
+            let options = SerializeOptions::new().serialize_none_to_null(false);
+            lua.to_value_with(&this.0.last_events()?, options)
+        });
+
+        methods.add_method("budget_remaining", |_, this, _: ()| Ok(this.0.budget_remaining()?));
+
+        methods.add_method("wait_for_update", |_, this, _: ()| Ok(this.0.wait_for_update()?));
+
+        methods.add_method("mod_on", |_, this, (mod_id, target): (String, Position)| {
+            this.0.mod_action(mod_id, UnitActionContainer::Pos(target))?;
             Ok(())
         });
 
@@ -174,8 +357,8 @@ where
             Ok(())
         });
 
-        methods.add_method_mut("add_mod", |_, this, (mod_id, action): (String, UnitActionContainer)| {
-            this.0.mod_action(mod_id, action)?;
+        methods.add_method_mut("add_mod", |_, this, (mod_type, mod_id): (ModType, String)| {
+            this.0.add_mod(mod_type, mod_id)?;
             Ok(())
         });
     }
@@ -1,18 +1,57 @@
 pub use crate::arsenal::engine::Move;
 use crate::{
-    arsenal::{engine::EngineType, Commit, UnitActionContainer, UnitMod, UnitModContainter},
+    arsenal::{
+        engine::{EngineType, Route, Step},
+        longest_delay, Commit, Energy, UnitActionContainer, UnitMod, UnitModContainter, UnitPart, UnitScore,
+        UnitStatus,
+    },
     error::{KResult, KikanError},
+    event::{Event, TickEvent},
+    map::{file::MapFile, Map, Terrain},
+    notify::{Listener, Notifier},
+    replay::{Order, Recorder, UnitSpec, World},
+    snapshot::{Snapshot, UnitState, SNAPSHOT_VERSION},
+    topology::Topology,
 };
-use bus::{Bus, BusReader};
 use mlua::UserData;
+use serde::{Deserialize, Serialize};
 use std::{
-    collections::{HashMap, HashSet, VecDeque},
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque},
+    io::Write,
+    mem,
     sync::{Arc, Mutex},
 };
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(from = "XY", into = "XY")]
 pub struct Position(pub i32, pub i32);
 
+/// `{x, y}` for scripts and files
+#[derive(Serialize, Deserialize)]
+struct XY {
+    x: i32,
+    y: i32,
+}
+
+impl From<XY> for Position {
+    fn from(xy: XY) -> Self {
+        Position(xy.x, xy.y)
+    }
+}
+
+impl From<Position> for XY {
+    fn from(pos: Position) -> Self {
+        XY { x: pos.0, y: pos.1 }
+    }
+}
+
+impl Position {
+    /// Manhattan distance, see [`Topology::distance`] for ranges.
+    pub fn distance(&self, other: &Position) -> usize {
+        ((self.0 - other.0).unsigned_abs() + (self.1 - other.1).unsigned_abs()) as usize
+    }
+}
+
 impl UserData for Position {
     fn add_fields<'lua, F: mlua::UserDataFields<'lua, Self>>(fields: &mut F) {
         fields.add_field_method_get("x", |_, this| Ok(this.0));
@@ -20,9 +59,24 @@ impl UserData for Position {
     }
 }
 
+pub const DEFAULT_HEALTH: u32 = 100;
+pub const DEFAULT_ENERGY: Energy = 100;
+/// energy a unit gets back every tick
+pub const DEFAULT_ENERGY_REGEN: Energy = 2;
+/// calls of the start position generator before giving up on it
+const START_POS_TRIES: usize = 64;
+
 pub struct UnitOrigin {
-    pub(crate) engine: Option<Box<dyn UnitMod<Move> + Send>>,
+    pub(crate) engine: Option<EngineType>,
     pub(crate) mods: HashMap<String, UnitModContainter>,
+    pub(crate) health: u32,
+    pub(crate) armor: u32,
+    pub(crate) max_energy: Energy,
+    pub(crate) energy_regen: Energy,
+    pub(crate) name: Option<String>,
+    pub(crate) team: Option<String>,
+    /// placed here instead of a generated start position
+    pub(crate) start: Option<Position>,
 }
 
 impl UnitOrigin {
@@ -30,11 +84,50 @@ impl UnitOrigin {
         Self {
             engine: None,
             mods: HashMap::new(),
+            health: DEFAULT_HEALTH,
+            armor: 0,
+            max_energy: DEFAULT_ENERGY,
+            energy_regen: DEFAULT_ENERGY_REGEN,
+            name: None,
+            team: None,
+            start: None,
         }
     }
 
+    pub fn set_name(&mut self, name: String) -> &mut Self {
+        self.name = Some(name);
+        self
+    }
+
+    pub fn set_team(&mut self, team: String) -> &mut Self {
+        self.team = Some(team);
+        self
+    }
+
+    pub fn set_start(&mut self, pos: Position) -> &mut Self {
+        self.start = Some(pos);
+        self
+    }
+
+    pub fn set_health(&mut self, health: u32) -> &mut Self {
+        self.health = health;
+        self
+    }
+
+    pub fn set_armor(&mut self, armor: u32) -> &mut Self {
+        self.armor = armor;
+        self
+    }
+
+    /// Units start full.
+    pub fn set_energy(&mut self, max_energy: Energy, energy_regen: Energy) -> &mut Self {
+        self.max_energy = max_energy;
+        self.energy_regen = energy_regen;
+        self
+    }
+
     pub fn set_engine(&mut self, engine: EngineType) -> &mut Self {
-        self.engine = Some(engine.into_engine());
+        self.engine = Some(engine);
         self
     }
 
@@ -44,14 +137,33 @@ impl UnitOrigin {
     }
 
     pub(crate) fn build(self, pos: Position) -> KResult<Unit> {
+        let engine_type = self.engine.ok_or(KikanError::MissingUnitPart("Engine"))?;
         Ok(Unit {
             pos,
-            engine: self.engine.ok_or(KikanError::MissingUnitPart("Engine"))?,
+            engine: engine_type.into_engine(),
+            engine_type,
             mods: self.mods,
+            health: self.health,
+            armor: self.armor + engine_type.spec().armor,
+            energy: self.max_energy,
+            max_energy: self.max_energy,
+            energy_regen: self.energy_regen,
+            name: self.name,
+            team: self.team,
+            damage_dealt: 0,
+            crashed: false,
+            path: VecDeque::new(),
+            path_interrupted: false,
         })
     }
 }
 
+impl UnitPart for UnitOrigin {
+    fn score(&self) -> UnitScore {
+        self.engine.map_or(0, |engine| engine.spec().score) + self.mods.score()
+    }
+}
+
 impl Default for UnitOrigin {
     fn default() -> Self {
         Self::new()
@@ -64,32 +176,191 @@ impl Default for UnitOrigin {
 /// 0 y →
 pub struct Unit {
     pub(crate) pos: Position,
-    pub(crate) engine: Box<dyn UnitMod<Move> + Send>,
+    pub(crate) engine: Box<dyn UnitMod<Step> + Send>,
+    pub(crate) engine_type: EngineType,
     pub(crate) mods: HashMap<String, UnitModContainter>,
+    pub(crate) health: u32,
+    /// flat reduction applied to every hit
+    pub(crate) armor: u32,
+    /// paid for every action of the engine and mods
+    pub(crate) energy: Energy,
+    pub(crate) max_energy: Energy,
+    pub(crate) energy_regen: Energy,
+    pub(crate) name: Option<String>,
+    pub(crate) team: Option<String>,
+    pub(crate) damage_dealt: u32,
+    /// hit something since the script last asked
+    pub(crate) crashed: bool,
+    /// moves left of the path being followed
+    pub(crate) path: VecDeque<Move>,
+    /// a path was cut short since the script last asked
+    pub(crate) path_interrupted: bool,
 }
 
 pub type UnitId = u32;
 
+impl UnitPart for Unit {
+    fn score(&self) -> UnitScore {
+        self.engine.score() + self.mods.score()
+    }
+}
+
 impl Unit {
     pub fn builder() -> UnitOrigin {
         UnitOrigin::new()
     }
 
-    fn plan_move(&mut self, next_move: Move) -> KResult<Box<dyn Commit>> {
+    fn plan_move(&mut self, next_move: Move, terrain: Terrain, topology: Topology) -> KResult<Box<dyn Commit>> {
         self.engine.status()?.operational_or_err()?;
-        self.engine.action(next_move)
+        self.engine.powered_action(
+            Step {
+                direction: next_move,
+                terrain,
+                topology,
+            },
+            &mut self.energy,
+        )
     }
 
     fn apply_move(&mut self, new_pos: Position) {
         self.pos = new_pos;
     }
 
-    fn take_action(&mut self, mod_id: String, action: UnitActionContainer) -> KResult<Box<dyn Commit>> {
+    fn take_action(
+        &mut self,
+        mod_id: String,
+        action: UnitActionContainer,
+        topology: Topology,
+    ) -> KResult<Box<dyn Commit>> {
         let umod = self.mods.get_mut(&mod_id).ok_or(KikanError::MissingUnitMod(mod_id))?;
-        umod.take_action(action)
+        umod.take_action(self.pos, topology, action, &mut self.energy)
+    }
+
+    fn crash(&mut self) {
+        self.crashed = true;
+        self.engine.jolt();
+    }
+
+    fn save(&self, id: UnitId) -> UnitState {
+        UnitState {
+            id,
+            pos: self.pos,
+            engine: self.engine.save(),
+            mods: self.mods.iter().map(|(id, umod)| (id.clone(), umod.save())).collect(),
+            health: self.health,
+            armor: self.armor,
+            energy: self.energy,
+            max_energy: self.max_energy,
+            energy_regen: self.energy_regen,
+            name: self.name.clone(),
+            team: self.team.clone(),
+            damage_dealt: self.damage_dealt,
+            crashed: self.crashed,
+            path: self.path.iter().copied().collect(),
+            path_interrupted: self.path_interrupted,
+        }
+    }
+
+    fn restore(state: UnitState) -> KResult<Self> {
+        let (engine_type, engine) = state.engine.into_engine()?;
+        let mods = state
+            .mods
+            .into_iter()
+            .map(|(id, umod)| Ok((id, umod.into_mod()?)))
+            .collect::<KResult<_>>()?;
+        Ok(Self {
+            pos: state.pos,
+            engine,
+            engine_type,
+            mods,
+            health: state.health,
+            armor: state.armor,
+            energy: state.energy,
+            max_energy: state.max_energy,
+            energy_regen: state.energy_regen,
+            name: state.name,
+            team: state.team,
+            damage_dealt: state.damage_dealt,
+            crashed: state.crashed,
+            path: state.path.into(),
+            path_interrupted: state.path_interrupted,
+        })
+    }
+
+    fn tick(&mut self) {
+        if !self.is_destroyed() {
+            self.energy = (self.energy + self.energy_regen).min(self.max_energy);
+        }
+        self.engine.tick();
+        for umod in self.mods.values_mut() {
+            umod.tick();
+        }
+    }
+
+    pub fn position(&self) -> Position {
+        self.pos
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    pub fn team(&self) -> Option<&str> {
+        self.team.as_deref()
+    }
+
+    pub fn health(&self) -> u32 {
+        self.health
+    }
+
+    pub fn energy(&self) -> Energy {
+        self.energy
+    }
+
+    pub fn damage_dealt(&self) -> u32 {
+        self.damage_dealt
+    }
+
+    pub fn is_destroyed(&self) -> bool {
+        self.health == 0
+    }
+
+    /// Returns the health actually lost, the parts of a destroyed unit are left online.
+    fn take_damage(&mut self, damage: u32) -> u32 {
+        let lost = damage.saturating_sub(self.armor).min(self.health);
+        self.health -= lost;
+        lost
+    }
+
+    /// Returns the parts which were still online, `"engine"` for the engine.
+    fn shut_down(&mut self) -> Vec<String> {
+        // parts which are already offline are fine
+        let mut parts = Vec::new();
+        if self.engine.mark_as_offline().is_ok() {
+            parts.push("engine".to_string());
+        }
+        let mut mods: Vec<_> = self.mods.iter_mut().collect();
+        mods.sort_unstable_by(|a, b| a.0.cmp(b.0));
+        for (mod_id, umod) in mods {
+            if umod.mark_as_offline().is_ok() {
+                parts.push(mod_id.clone());
+            }
+        }
+        parts
     }
 }
 
+/// Optional rules of a match.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Rules {
+    /// units hurt each other when they collide, by [`EngineType::mass`]
+    pub collision_damage: bool,
+    /// a unit driving into a standing one no heavier than itself pushes it one cell on, if there is room
+    pub pushing: bool,
+    /// most [`UnitPart::score`] points a unit may be built from, `None` for no limit
+    pub budget: Option<UnitScore>,
+}
+
 pub struct PosConfig {
     pub number: u32,
 }
@@ -100,7 +371,18 @@ pub struct Kikan {
     commits: VecDeque<Vec<Box<dyn Commit>>>,
     move_commits: HashMap<UnitId, Position>,
     start_pos: Box<dyn Fn() -> Position + Send>,
-    update_bus: Bus<()>,
+    update_bus: Notifier<TickEvent>,
+    tick: u64,
+    /// what happened in the last update
+    events: Vec<Event>,
+    /// what scripts did since, reported with the next update
+    pending: Vec<Event>,
+    rules: Rules,
+    map: Map,
+    topology: Topology,
+    recorder: Option<Recorder>,
+    /// why the replay stopped being written
+    recording_failed: Option<String>,
 }
 
 impl Kikan {
@@ -108,31 +390,212 @@ impl Kikan {
     where
         F: Fn() -> Position + 'static + Send,
     {
-        let kikan = Kikan {
+        Arc::new(Mutex::new(Self::new(start_pos)))
+    }
+
+    pub fn new<F>(start_pos: F) -> Self
+    where
+        F: Fn() -> Position + 'static + Send,
+    {
+        Kikan {
             count: 0,
             units: HashMap::new(),
             commits: VecDeque::with_capacity(1024),
             move_commits: HashMap::default(),
             start_pos: Box::new(start_pos),
-            update_bus: Bus::new(42), // every thing
-        };
-        Arc::new(Mutex::new(kikan))
+            update_bus: Notifier::new(42),
+            tick: 0,
+            events: Vec::new(),
+            pending: Vec::new(),
+            rules: Rules::default(),
+            map: Map::open(),
+            topology: Topology::default(),
+            recorder: None,
+            recording_failed: None,
+        }
+    }
+
+    /// Units take the spawns of the map in order, then any free cell.
+    pub fn kikan_on_map(file: &MapFile) -> Arc<Mutex<Self>> {
+        let spawns = Mutex::new(
+            file.spawns
+                .iter()
+                .map(|spawn| spawn.pos)
+                .collect::<Vec<_>>()
+                .into_iter(),
+        );
+        // maps from files are bounded, so the fallback is never free
+        let kikan = Self::kikan_in_a_shell(move || spawns.lock().unwrap().next().unwrap_or(Position(-1, -1)));
+        {
+            let mut kikan = kikan.lock().unwrap();
+            kikan.set_map(file.map.clone());
+            kikan.set_topology(file.topology.unwrap_or_default());
+        }
+        kikan
+    }
+
+    pub fn set_rules(&mut self, rules: Rules) {
+        self.rules = rules;
+    }
+
+    pub fn rules(&self) -> Rules {
+        self.rules
+    }
+
+    /// Writes a replay of the match to `out`, see [`crate::replay`].
+    /// The map, topology and rules are taken as they are now.
+    pub fn record_to(&mut self, out: Box<dyn Write + Send>) -> KResult<()> {
+        if self.tick > 0 || !self.units.is_empty() {
+            return Err(KikanError::RecordingLate);
+        }
+        self.recorder = Some(Recorder::new(out, World::of(self))?);
+        Ok(())
+    }
+
+    /// The replay could not be written, it ends at the update that failed.
+    pub fn recording_failed(&self) -> Option<&str> {
+        self.recording_failed.as_deref()
+    }
+
+    /// Saves the world between two updates, listeners and recording are left out.
+    pub fn snapshot(&self) -> Snapshot {
+        let mut units: Vec<UnitState> = self.units.iter().map(|(id, unit)| unit.save(*id)).collect();
+        units.sort_unstable_by_key(|unit| unit.id);
+        // the front of the queue resolves on the next update
+        let commits = self
+            .commits
+            .iter()
+            .enumerate()
+            .filter(|(_, seat)| !seat.is_empty())
+            .map(|(i, seat)| {
+                (
+                    self.tick + 1 + i as u64,
+                    seat.iter().map(|commit| commit.save()).collect(),
+                )
+            })
+            .collect();
+        Snapshot {
+            version: SNAPSHOT_VERSION,
+            tick: self.tick,
+            world: World::of(self),
+            next_id: self.count,
+            units,
+            commits,
+            moves: self.move_commits.iter().map(|(id, pos)| (*id, *pos)).collect(),
+            events: self.events.clone(),
+            pending: self.pending.clone(),
+        }
+    }
+
+    /// A fresh world which goes on from `snapshot`, new units start at (0, 0) unless placed.
+    pub fn restore(snapshot: &Snapshot) -> KResult<Self> {
+        if snapshot.version != SNAPSHOT_VERSION {
+            return Err(KikanError::SnapshotVersion(u64::from(snapshot.version)));
+        }
+        let mut kikan = snapshot.world.build();
+        kikan.tick = snapshot.tick;
+        kikan.count = snapshot.next_id;
+        for state in snapshot.units.iter().cloned() {
+            if state.id >= snapshot.next_id || kikan.units.contains_key(&state.id) {
+                return Err(KikanError::BadSnapshot(format!("unit id {}", state.id)));
+            }
+            kikan.units.insert(state.id, Unit::restore(state)?);
+        }
+        for (tick, seat) in snapshot.commits.iter() {
+            if *tick <= snapshot.tick {
+                return Err(KikanError::BadSnapshot(format!("commit on past tick {}", tick)));
+            }
+            if tick - snapshot.tick > longest_delay() as u64 {
+                return Err(KikanError::BadSnapshot(format!("commit on far tick {}", tick)));
+            }
+            let at = (tick - snapshot.tick - 1) as usize;
+            if kikan.commits.len() <= at {
+                kikan.commits.resize_with(at + 1, Default::default);
+            }
+            for commit in seat.iter().cloned() {
+                kikan.commits[at].push(commit.restore()?);
+            }
+        }
+        kikan.move_commits = snapshot.moves.iter().map(|(id, pos)| (*id, *pos)).collect();
+        kikan.events = snapshot.events.clone();
+        kikan.pending = snapshot.pending.clone();
+        Ok(kikan)
+    }
+
+    fn record(&mut self, order: impl FnOnce() -> Order) {
+        if let Some(recorder) = &mut self.recorder {
+            recorder.order(order());
+        }
+    }
+
+    /// Units already placed are not checked against the new map.
+    pub fn set_map(&mut self, map: Map) {
+        self.map = map;
+    }
+
+    pub fn map(&self) -> &Map {
+        &self.map
+    }
+
+    /// Only change it before units are placed.
+    pub fn set_topology(&mut self, topology: Topology) {
+        self.topology = topology;
+    }
+
+    pub fn topology(&self) -> Topology {
+        self.topology
+    }
+
+    pub fn terrain_at(&self, pos: Position) -> Terrain {
+        self.map.terrain(pos)
+    }
+
+    pub fn budget(&self) -> Option<UnitScore> {
+        self.rules.budget
+    }
+
+    pub fn check_budget(&self, unit: &UnitOrigin) -> KResult<()> {
+        match self.rules.budget {
+            Some(budget) if unit.score() > budget => Err(KikanError::OverBudget(unit.score(), budget)),
+            _ => Ok(()),
+        }
     }
 
     pub fn add_unit(&mut self, pos: Position, unit: UnitOrigin) -> KResult<UnitId> {
+        self.check_budget(&unit)?;
+        if !self.map.is_free(pos) {
+            return Err(KikanError::BlockedCell);
+        }
         if self.units.iter().any(|(_, v)| v.pos == pos) {
             return Err(KikanError::AlreadyUnitHere);
         }
+        let spec = self.recorder.is_some().then(|| UnitSpec::of(&unit));
+        let unit = unit.build(pos)?;
         let id = self.count;
         self.count += 1;
-        let unit = unit.build(pos)?;
         self.units.insert(id, unit);
+        if let Some(unit) = spec {
+            self.record(|| Order::AddUnit { id, pos, unit });
+        }
         Ok(id)
     }
 
     pub fn plan_unit_move(&mut self, unit_id: UnitId, next_move: Move) -> KResult<()> {
+        self.plan_step(unit_id, next_move)?;
+        self.record(|| Order::Move {
+            unit: unit_id,
+            direction: next_move,
+        });
+        Ok(())
+    }
+
+    fn plan_step(&mut self, unit_id: UnitId, next_move: Move) -> KResult<()> {
         let unit = self.units.get_mut(&unit_id).ok_or(KikanError::GhostUnit)?;
-        let mut commit = unit.plan_move(next_move)?;
+        let to = self
+            .topology
+            .neighbour(unit.pos, next_move)
+            .ok_or(KikanError::InvalidMove)?;
+        let mut commit = unit.plan_move(next_move, self.map.terrain(to), self.topology)?;
         commit.fill_unit_id(unit_id);
         self.add_commit(commit);
         Ok(())
@@ -143,43 +606,348 @@ impl Kikan {
         Some(unit.pos)
     }
 
+    /// Sorted, so callers walk units in a stable order.
+    pub fn unit_ids(&self) -> Vec<UnitId> {
+        let mut ids: Vec<UnitId> = self.units.keys().copied().collect();
+        ids.sort_unstable();
+        ids
+    }
+
+    pub fn get_unit(&self, unit_id: UnitId) -> Option<&Unit> {
+        self.units.get(&unit_id)
+    }
+
+    pub fn get_unit_at(&self, pos: Position) -> Option<UnitId> {
+        self.units.iter().find(|(_, unit)| unit.pos == pos).map(|(id, _)| *id)
+    }
+
+    pub fn get_unit_health(&self, unit_id: UnitId) -> Option<u32> {
+        let unit = self.units.get(&unit_id)?;
+        Some(unit.health)
+    }
+
+    pub fn get_unit_energy(&self, unit_id: UnitId) -> Option<Energy> {
+        let unit = self.units.get(&unit_id)?;
+        Some(unit.energy)
+    }
+
+    pub fn is_unit_alive(&self, unit_id: UnitId) -> bool {
+        self.units.get(&unit_id).is_some_and(|unit| !unit.is_destroyed())
+    }
+
+    /// The unit keeps its place but can not act anymore.
+    pub fn disqualify_unit(&mut self, unit_id: UnitId) -> KResult<()> {
+        let unit = self.units.get_mut(&unit_id).ok_or(KikanError::GhostUnit)?;
+        let parts = unit.shut_down();
+        self.pending
+            .extend(parts.into_iter().map(|part| Event::ModOffline { unit: unit_id, part }));
+        self.record(|| Order::Disqualify { unit: unit_id });
+        Ok(())
+    }
+
+    /// Destroyed units stay on the field as wrecks.
+    /// The health lost is credited to `source` if it is given.
+    pub fn damage_unit(&mut self, unit_id: UnitId, damage: u32, source: Option<UnitId>) -> KResult<()> {
+        let unit = self.units.get_mut(&unit_id).ok_or(KikanError::GhostUnit)?;
+        let alive = !unit.is_destroyed();
+        let lost = unit.take_damage(damage);
+        if alive {
+            self.events.push(Event::Hit {
+                unit: unit_id,
+                by: source,
+                damage: lost,
+            });
+        }
+        if alive && unit.is_destroyed() {
+            self.events.push(Event::Destroyed {
+                unit: unit_id,
+                by: source,
+            });
+            let parts = unit.shut_down();
+            self.events
+                .extend(parts.into_iter().map(|part| Event::ModOffline { unit: unit_id, part }));
+        }
+        if let Some(source) = source.and_then(|id| self.units.get_mut(&id)) {
+            source.damage_dealt += lost;
+        }
+        Ok(())
+    }
+
+    /// Moves are cancelled when two units aim at the same cell, when two units swap cells,
+    /// or when the cell is held by a unit which does not leave it.
+    /// Units moving in a closed cycle all move.
     fn apply_move(&mut self) {
-        // pos to id
-        let mut new_pos: HashMap<Position, UnitId> = HashMap::new();
-        // which unit can be updated
-        let mut new_pos_avaliable: HashSet<UnitId> = HashSet::new();
-
-        for id in self.units.keys() {
-            let now_pos = self.get_unit_position(*id).unwrap();
-            let next_pos = self.move_commits.remove(id).unwrap_or(now_pos);
-            if let Some(another_unit) = new_pos.get(&next_pos) {
-                new_pos_avaliable.remove(another_unit);
-            } else {
-                new_pos.insert(next_pos, *id);
-                new_pos_avaliable.insert(*id);
+        let move_commits = mem::take(&mut self.move_commits);
+        let moving: BTreeMap<UnitId, Position> = move_commits
+            .into_iter()
+            .filter(|(id, to)| {
+                self.units
+                    .get(id)
+                    .is_some_and(|unit| !unit.is_destroyed() && unit.pos != *to)
+            })
+            .collect();
+        let occupied: HashMap<Position, UnitId> = self.units.iter().map(|(id, unit)| (unit.pos, *id)).collect();
+        let mut blocked: BTreeSet<UnitId> = BTreeSet::new();
+        // unit, with, at
+        let mut collisions: Vec<(UnitId, UnitId, Position)> = Vec::new();
+
+        let wall_hits: Vec<(UnitId, Position)> = moving
+            .iter()
+            .filter(|(_, to)| !self.map.is_free(**to))
+            .map(|(id, to)| (*id, *to))
+            .collect();
+        blocked.extend(wall_hits.iter().map(|(id, _)| *id));
+
+        let mut claims: BTreeMap<Position, Vec<UnitId>> = BTreeMap::new();
+        for (id, to) in moving.iter().filter(|(id, _)| !blocked.contains(id)) {
+            claims.entry(*to).or_default().push(*id);
+        }
+        for (at, ids) in claims.iter().filter(|(_, ids)| ids.len() > 1) {
+            for id in ids.iter() {
+                blocked.insert(*id);
+                for with in ids.iter().filter(|with| *with != id) {
+                    collisions.push((*id, *with, *at));
+                }
+            }
+        }
+
+        // units passing through each other
+        for (id, to) in moving.iter() {
+            if let Some(with) = occupied.get(to) {
+                if !blocked.contains(id) && moving.get(with) == Some(&self.units[id].pos) {
+                    blocked.insert(*id);
+                    collisions.push((*id, *with, *to));
+                }
+            }
+        }
+
+        // a blocked unit blocks whoever follows it, until nothing changes
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (id, to) in moving.iter() {
+                if blocked.contains(id) {
+                    continue;
+                }
+                if let Some(with) = occupied.get(to) {
+                    if !moving.contains_key(with) || blocked.contains(with) {
+                        blocked.insert(*id);
+                        collisions.push((*id, *with, *to));
+                        changed = true;
+                    }
+                }
             }
         }
-        for (pos, id) in new_pos.into_iter() {
-            if new_pos_avaliable.contains(&id) {
-                self.units.get_mut(&id).expect("Ghost unit!").apply_move(pos);
+
+        let pushes = if self.rules.pushing {
+            self.find_pushes(&moving, &blocked, &occupied, &collisions)
+        } else {
+            Vec::new()
+        };
+        let mut events = Vec::new();
+        for (id, to) in moving.iter().filter(|(id, _)| !blocked.contains(id)) {
+            let unit = self.units.get_mut(id).expect("Ghost unit!");
+            events.push(Event::Moved {
+                unit: *id,
+                from: unit.pos,
+                to: *to,
+            });
+            unit.apply_move(*to);
+        }
+        for (unit, with, at, to) in pushes.iter().copied() {
+            for (id, to) in [(with, to), (unit, at)] {
+                let moved = self.units.get_mut(&id).expect("Ghost unit!");
+                events.push(Event::Moved {
+                    unit: id,
+                    from: moved.pos,
+                    to,
+                });
+                moved.apply_move(to);
+            }
+            events.push(Event::Pushed { unit, with, to });
+        }
+        collisions.retain(|(unit, _, _)| !pushes.iter().any(|push| push.0 == *unit));
+
+        // hits are reported after the crashes causing them
+        let mut hits = Vec::new();
+        for (unit, at) in wall_hits {
+            if self.rules.collision_damage {
+                hits.push((unit, self.units[&unit].engine_type.mass() / 2, None));
+            }
+            self.units.get_mut(&unit).expect("Ghost unit!").crash();
+            events.push(Event::WallCollision { unit, at });
+        }
+
+        let mut crashes = HashSet::new();
+        for (unit, with, at) in collisions.iter().copied() {
+            // every crash is shared, both sides take half of what the other one weighs, once
+            if self.rules.collision_damage && crashes.insert((unit.min(with), unit.max(with))) {
+                let hit = |id: UnitId| self.units[&id].engine_type.mass() / 2;
+                hits.push((unit, hit(with), Some(with)));
+                hits.push((with, hit(unit), Some(unit)));
+            }
+            events.push(Event::Collision { unit, with, at });
+        }
+        for event in events.iter() {
+            if let Event::Collision { unit, with, .. } | Event::Pushed { unit, with, .. } = event {
+                for id in [unit, with] {
+                    self.units.get_mut(id).expect("Ghost unit!").crash();
+                }
             }
         }
+        self.events.extend(events);
+        for (unit, damage, source) in hits {
+            self.damage_unit(unit, damage, source).ok();
+        }
     }
 
-    pub fn gen_start_pos(&mut self) -> Position {
-        (self.start_pos)()
+    /// Blocked units which drove straight into a standing unit they can push.
+    /// Returns the pusher, the pushed unit, the cell taken over and where the pushed unit goes.
+    fn find_pushes(
+        &self,
+        moving: &BTreeMap<UnitId, Position>,
+        blocked: &BTreeSet<UnitId>,
+        occupied: &HashMap<Position, UnitId>,
+        collisions: &[(UnitId, UnitId, Position)],
+    ) -> Vec<(UnitId, UnitId, Position, Position)> {
+        // cells which hold a unit after this update
+        let mut taken: HashSet<Position> = self
+            .units
+            .iter()
+            .filter(|(id, _)| !moving.contains_key(id) || blocked.contains(id))
+            .map(|(_, unit)| unit.pos)
+            .chain(moving.iter().filter(|(id, _)| !blocked.contains(id)).map(|(_, to)| *to))
+            .collect();
+        let mut pushes = Vec::new();
+        for (unit, with, at) in collisions.iter().copied() {
+            let standing = !moving.contains_key(&with) && occupied.get(&at) == Some(&with);
+            let alone = collisions.iter().filter(|(_, other, _)| *other == with).count() == 1
+                && moving.values().filter(|to| **to == at).count() == 1;
+            if !standing || !alone || self.units[&with].is_destroyed() {
+                continue;
+            }
+            if self.units[&unit].engine_type.mass() < self.units[&with].engine_type.mass() {
+                continue;
+            }
+            let from = self.units[&unit].pos;
+            let to = Position(2 * at.0 - from.0, 2 * at.1 - from.1);
+            let passable = self.units[&with].engine_type.move_delay(self.map.terrain(to)).is_some();
+            if self.map.is_free(to) && passable && taken.insert(to) {
+                pushes.push((unit, with, at, to));
+            }
+        }
+        pushes
+    }
+
+    /// Number of updates so far.
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    /// Events of the last update.
+    pub fn last_events(&self) -> &[Event] {
+        &self.events
+    }
+
+    /// Asks `start_pos` a few times for a free cell, then takes the first free one on the map.
+    pub fn gen_start_pos(&mut self) -> KResult<Position> {
+        let usable = |kikan: &Self, pos: Position| kikan.map.is_free(pos) && kikan.get_unit_at(pos).is_none();
+        for _ in 0..START_POS_TRIES {
+            let pos = (self.start_pos)();
+            if usable(self, pos) {
+                return Ok(pos);
+            }
+        }
+        self.map
+            .free_cells()
+            .into_iter()
+            .find(|pos| usable(self, *pos))
+            .ok_or(KikanError::NoRoomForUnits(1))
+    }
+
+    /// Whether the unit hit something since the last call.
+    pub fn take_unit_crash(&mut self, id: UnitId) -> KResult<bool> {
+        let unit = self.units.get_mut(&id).ok_or(KikanError::GhostUnit)?;
+        Ok(mem::take(&mut unit.crashed))
     }
 
     pub fn is_unit_moving(&self, id: UnitId) -> KResult<bool> {
         let unit = self.units.get(&id).ok_or(KikanError::GhostUnit)?;
-        unit.engine.status().map(|st| st.is_busy())
+        Ok(!unit.path.is_empty() || unit.engine.status()?.is_busy())
+    }
+
+    /// Plans the first move right away, the others each time the one before is done.
+    pub fn plan_unit_path(&mut self, unit_id: UnitId, route: Route) -> KResult<()> {
+        let unit = self.units.get(&unit_id).ok_or(KikanError::GhostUnit)?;
+        let mut moves: VecDeque<Move> = match route {
+            Route::Moves(moves) => moves.into(),
+            Route::To(to) => self
+                .map
+                .find_path(unit.pos, to, unit.engine_type, self.topology)
+                .ok_or(KikanError::NoPath)?
+                .into(),
+        };
+        let recorded = self.recorder.is_some().then(|| moves.iter().copied().collect());
+        if let Some(first) = moves.pop_front() {
+            self.plan_step(unit_id, first)?;
+        }
+        let unit = self.get_unit_by_id(unit_id)?;
+        unit.path = moves;
+        unit.path_interrupted = false;
+        if let Some(moves) = recorded {
+            self.record(|| Order::Path { unit: unit_id, moves });
+        }
+        Ok(())
+    }
+
+    pub fn take_unit_path_interrupted(&mut self, id: UnitId) -> KResult<bool> {
+        let unit = self.units.get_mut(&id).ok_or(KikanError::GhostUnit)?;
+        Ok(mem::take(&mut unit.path_interrupted))
     }
 
-    pub fn wait_for_update(&mut self) -> BusReader<()> {
-        self.update_bus.add_rx()
+    /// Units that crashed or got pushed drop the rest of their path.
+    fn follow_paths(&mut self) {
+        let mut ids: Vec<UnitId> = self
+            .units
+            .iter()
+            .filter(|(_, unit)| !unit.path.is_empty())
+            .map(|(id, _)| *id)
+            .collect();
+        ids.sort_unstable();
+        for id in ids {
+            let knocked = self.events.iter().any(|event| match event {
+                Event::Collision { unit, .. } | Event::WallCollision { unit, .. } => *unit == id,
+                Event::Pushed { with, .. } => *with == id,
+                _ => false,
+            });
+            let unit = &self.units[&id];
+            let interrupted = match unit.engine.status() {
+                _ if knocked || unit.is_destroyed() => true,
+                Ok(UnitStatus::Operational) => {
+                    let next = self.units.get_mut(&id).expect("Ghost unit!").path.pop_front();
+                    next.is_some_and(|next| self.plan_step(id, next).is_err())
+                }
+                Ok(UnitStatus::Busy) => false,
+                Ok(UnitStatus::Offline) | Err(_) => true,
+            };
+            if interrupted {
+                let unit = self.units.get_mut(&id).expect("Ghost unit!");
+                let at = unit.pos;
+                unit.path.clear();
+                unit.path_interrupted = true;
+                self.events.push(Event::PathInterrupted { unit: id, at });
+            }
+        }
+    }
+
+    /// Listeners never hold up the world, ones too far behind miss the oldest ticks.
+    pub fn wait_for_update(&mut self) -> Listener<TickEvent> {
+        self.update_bus.listen()
     }
 
     pub fn update(&mut self) -> KResult<()> {
+        self.tick += 1;
+        self.events = mem::take(&mut self.pending);
         let mut res = Vec::new();
         if let Some(commits) = self.commits.pop_front() {
             for commit in commits {
@@ -187,7 +955,21 @@ impl Kikan {
             }
         };
         self.apply_move();
-        self.update_bus.broadcast(());
+        self.follow_paths();
+        for unit in self.units.values_mut() {
+            unit.tick();
+        }
+        self.update_bus.send(TickEvent {
+            tick: self.tick,
+            events: self.events.clone(),
+        });
+        if let Some(recorder) = &mut self.recorder {
+            if let Err(e) = recorder.tick(self.tick, &self.events) {
+                // the match goes on without a replay
+                self.recorder = None;
+                self.recording_failed = Some(e.to_string());
+            }
+        }
         res.into_iter().collect()
     }
 
@@ -216,8 +998,20 @@ impl Kikan {
 
     pub fn unit_mod_action(&mut self, unit_id: UnitId, mod_id: String, action: UnitActionContainer) -> KResult<()> {
         let unit = self.units.get_mut(&unit_id).ok_or(KikanError::GhostUnit)?;
-        let commit = unit.take_action(mod_id, action)?;
+        let mut commit = unit.take_action(mod_id.clone(), action, self.topology)?;
+        commit.fill_unit_id(unit_id);
         self.add_commit(commit);
+        let UnitActionContainer::Pos(target) = action;
+        self.record(|| Order::ModAction {
+            unit: unit_id,
+            mod_id: mod_id.clone(),
+            target,
+        });
+        self.pending.push(Event::ShotFired {
+            unit: unit_id,
+            weapon: mod_id,
+            target,
+        });
         Ok(())
     }
 }
@@ -225,6 +1019,7 @@ impl Kikan {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::arsenal::kinetic_weapon::{KineticWeapon, KineticWeaponCommit, KineticWeaponType};
 
     fn test_kikan() -> Kikan {
         Kikan {
@@ -233,7 +1028,15 @@ mod tests {
             commits: VecDeque::default(),
             move_commits: HashMap::default(),
             start_pos: Box::new(|| Position(0, 0)),
-            update_bus: Bus::new(42),
+            update_bus: Notifier::new(42),
+            tick: 0,
+            events: Vec::new(),
+            pending: Vec::new(),
+            rules: Rules::default(),
+            map: Map::open(),
+            topology: Topology::default(),
+            recorder: None,
+            recording_failed: None,
         }
     }
 
@@ -334,6 +1137,742 @@ mod tests {
         assert_eq!(pos_u1, Position(0, 1));
     }
 
+    fn shot(target: Position, damage: u32) -> Box<dyn Commit> {
+        Box::new(KineticWeaponCommit {
+            delay: Box::new(|_| 1),
+            distance: 0,
+            target,
+            damage,
+            shooter: None,
+        })
+    }
+
+    #[test]
+    fn unit_hit() {
+        let mut kikan = test_kikan();
+        let mut unit = Unit::builder();
+        unit.set_engine(EngineType::STE).set_armor(5);
+        let u0 = kikan.add_unit(Position(0, 0), unit).unwrap();
+
+        kikan.add_commit(shot(Position(0, 0), 30));
+        kikan.add_commit(shot(Position(0, 1), 30));
+        for _ in 0..3 {
+            kikan.update().unwrap();
+        }
+        assert_eq!(kikan.get_unit_health(u0), Some(DEFAULT_HEALTH - 25));
+        assert!(kikan.is_unit_alive(u0));
+    }
+
+    #[test]
+    fn unit_destroyed() {
+        let mut kikan = test_kikan();
+        let mut unit = Unit::builder();
+        unit.set_engine(EngineType::STE);
+        let u0 = kikan.add_unit(Position(0, 0), unit).unwrap();
+
+        kikan.plan_unit_move(u0, Move::N).unwrap();
+        kikan.add_commit(shot(Position(0, 0), DEFAULT_HEALTH));
+        for _ in 0..20 {
+            kikan.update().unwrap();
+        }
+        assert_eq!(kikan.get_unit_health(u0), Some(0));
+        assert!(!kikan.is_unit_alive(u0));
+        assert_eq!(kikan.get_unit_position(u0), Some(Position(0, 0)));
+        assert!(matches!(kikan.plan_unit_move(u0, Move::N), Err(KikanError::ModOffline)));
+    }
+
+    #[test]
+    fn unit_fire() {
+        let mut kikan = test_kikan();
+        let mut unit0 = Unit::builder();
+        unit0.set_engine(EngineType::STE).add_mods(
+            UnitModContainter::KineticWeapon(Box::new(KineticWeapon::new(KineticWeaponType::Cannon))),
+            "gun".to_string(),
+        );
+        let mut unit1 = Unit::builder();
+        unit1.set_engine(EngineType::STE);
+        let u0 = kikan.add_unit(Position(0, 0), unit0).unwrap();
+        let u1 = kikan.add_unit(Position(0, 3), unit1).unwrap();
+
+        let target = UnitActionContainer::Pos(Position(0, 3));
+        kikan.unit_mod_action(u0, "gun".to_string(), target).unwrap();
+        assert!(matches!(
+            kikan.unit_mod_action(u0, "gun".to_string(), target),
+            Err(KikanError::ModBusy)
+        ));
+        for _ in 0..4 {
+            kikan.update().unwrap();
+        }
+        assert_eq!(kikan.get_unit_health(u1), Some(DEFAULT_HEALTH));
+        kikan.update().unwrap();
+        assert_eq!(kikan.get_unit_health(u1), Some(DEFAULT_HEALTH - 40));
+        assert_eq!(kikan.get_unit(u0).unwrap().damage_dealt(), 40);
+    }
+
+    #[test]
+    fn tick_events() {
+        let mut kikan = test_kikan();
+        let mut unit0 = Unit::builder();
+        unit0.set_engine(EngineType::STE).add_mods(
+            UnitModContainter::KineticWeapon(Box::new(KineticWeapon::new(KineticWeaponType::Cannon))),
+            "gun".to_string(),
+        );
+        let mut unit1 = Unit::builder();
+        unit1.set_engine(EngineType::STE).set_health(30);
+        let u0 = kikan.add_unit(Position(0, 0), unit0).unwrap();
+        let u1 = kikan.add_unit(Position(0, 3), unit1).unwrap();
+        let mut reader = kikan.wait_for_update();
+
+        let target = Position(0, 3);
+        kikan
+            .unit_mod_action(u0, "gun".to_string(), UnitActionContainer::Pos(target))
+            .unwrap();
+        kikan.plan_unit_move(u0, Move::N).unwrap();
+        kikan.update().unwrap();
+        let shot = Event::ShotFired {
+            unit: u0,
+            weapon: "gun".to_string(),
+            target,
+        };
+        assert_eq!(
+            reader.recv().unwrap(),
+            TickEvent {
+                tick: 1,
+                events: vec![shot]
+            }
+        );
+        for _ in 0..4 {
+            kikan.update().unwrap();
+        }
+        let hit = reader.iter().take(4).find(|tick| !tick.events.is_empty()).unwrap();
+        assert_eq!(hit.tick, 5);
+        assert_eq!(
+            hit.events,
+            vec![
+                Event::Hit {
+                    unit: u1,
+                    by: Some(u0),
+                    damage: 30
+                },
+                Event::Destroyed { unit: u1, by: Some(u0) },
+                Event::ModOffline {
+                    unit: u1,
+                    part: "engine".to_string()
+                },
+            ]
+        );
+        for _ in 0..6 {
+            kikan.update().unwrap();
+        }
+        let moved = reader.iter().take(6).flat_map(|tick| tick.events).collect::<Vec<_>>();
+        assert_eq!(
+            moved,
+            vec![Event::Moved {
+                unit: u0,
+                from: Position(0, 0),
+                to: Position(1, 0)
+            }]
+        );
+
+        kikan.disqualify_unit(u0).unwrap();
+        kikan.update().unwrap();
+        assert_eq!(kikan.tick(), 12);
+        assert_eq!(
+            kikan.last_events(),
+            &[
+                Event::ModOffline {
+                    unit: u0,
+                    part: "engine".to_string()
+                },
+                Event::ModOffline {
+                    unit: u0,
+                    part: "gun".to_string()
+                },
+            ]
+        );
+    }
+
+    struct CollisionCase {
+        name: &'static str,
+        /// start and target of every unit
+        units: &'static [(Position, Option<Position>)],
+        wrecks: &'static [UnitId],
+        end: &'static [Position],
+        blocked: &'static [UnitId],
+    }
+
+    const COLLISION_CASES: &[CollisionCase] = &[
+        CollisionCase {
+            name: "free move",
+            units: &[(Position(0, 0), Some(Position(1, 0)))],
+            wrecks: &[],
+            end: &[Position(1, 0)],
+            blocked: &[],
+        },
+        CollisionCase {
+            name: "same target",
+            units: &[
+                (Position(0, 0), Some(Position(1, 0))),
+                (Position(2, 0), Some(Position(1, 0))),
+            ],
+            wrecks: &[],
+            end: &[Position(0, 0), Position(2, 0)],
+            blocked: &[0, 1],
+        },
+        CollisionCase {
+            name: "three on one",
+            units: &[
+                (Position(0, 0), Some(Position(1, 0))),
+                (Position(2, 0), Some(Position(1, 0))),
+                (Position(1, 1), Some(Position(1, 0))),
+            ],
+            wrecks: &[],
+            end: &[Position(0, 0), Position(2, 0), Position(1, 1)],
+            blocked: &[0, 1, 2],
+        },
+        CollisionCase {
+            name: "stationary",
+            units: &[(Position(0, 0), Some(Position(1, 0))), (Position(1, 0), None)],
+            wrecks: &[],
+            end: &[Position(0, 0), Position(1, 0)],
+            blocked: &[0],
+        },
+        CollisionCase {
+            name: "wreck",
+            units: &[
+                (Position(0, 0), Some(Position(1, 0))),
+                (Position(1, 0), Some(Position(2, 0))),
+            ],
+            wrecks: &[1],
+            end: &[Position(0, 0), Position(1, 0)],
+            blocked: &[0],
+        },
+        CollisionCase {
+            name: "swap",
+            units: &[
+                (Position(0, 0), Some(Position(1, 0))),
+                (Position(1, 0), Some(Position(0, 0))),
+            ],
+            wrecks: &[],
+            end: &[Position(0, 0), Position(1, 0)],
+            blocked: &[0, 1],
+        },
+        CollisionCase {
+            name: "chain",
+            units: &[
+                (Position(0, 0), Some(Position(1, 0))),
+                (Position(1, 0), Some(Position(2, 0))),
+                (Position(2, 0), Some(Position(3, 0))),
+            ],
+            wrecks: &[],
+            end: &[Position(1, 0), Position(2, 0), Position(3, 0)],
+            blocked: &[],
+        },
+        CollisionCase {
+            name: "blocked chain",
+            units: &[
+                (Position(0, 0), Some(Position(1, 0))),
+                (Position(1, 0), Some(Position(2, 0))),
+                (Position(2, 0), None),
+            ],
+            wrecks: &[],
+            end: &[Position(0, 0), Position(1, 0), Position(2, 0)],
+            blocked: &[0, 1],
+        },
+        CollisionCase {
+            name: "leader lost a contest",
+            units: &[
+                (Position(0, 0), Some(Position(1, 0))),
+                (Position(1, 0), Some(Position(2, 0))),
+                (Position(3, 0), Some(Position(2, 0))),
+            ],
+            wrecks: &[],
+            end: &[Position(0, 0), Position(1, 0), Position(3, 0)],
+            blocked: &[0, 1, 2],
+        },
+        CollisionCase {
+            name: "follow into swap",
+            units: &[
+                (Position(0, 0), Some(Position(1, 0))),
+                (Position(1, 0), Some(Position(2, 0))),
+                (Position(2, 0), Some(Position(1, 0))),
+            ],
+            wrecks: &[],
+            end: &[Position(0, 0), Position(1, 0), Position(2, 0)],
+            blocked: &[0, 1, 2],
+        },
+        CollisionCase {
+            name: "cycle",
+            units: &[
+                (Position(0, 0), Some(Position(1, 0))),
+                (Position(1, 0), Some(Position(1, 1))),
+                (Position(1, 1), Some(Position(0, 1))),
+                (Position(0, 1), Some(Position(0, 0))),
+            ],
+            wrecks: &[],
+            end: &[Position(1, 0), Position(1, 1), Position(0, 1), Position(0, 0)],
+            blocked: &[],
+        },
+        CollisionCase {
+            name: "cycle with a tail",
+            units: &[
+                (Position(0, 0), Some(Position(1, 0))),
+                (Position(1, 0), Some(Position(1, 1))),
+                (Position(1, 1), Some(Position(0, 1))),
+                (Position(0, 1), Some(Position(0, 0))),
+                (Position(-1, 0), Some(Position(0, 0))),
+            ],
+            wrecks: &[],
+            end: &[
+                Position(0, 0),
+                Position(1, 0),
+                Position(1, 1),
+                Position(0, 1),
+                Position(-1, 0),
+            ],
+            blocked: &[0, 1, 2, 3, 4],
+        },
+    ];
+
+    #[test]
+    fn collision_table() {
+        for case in COLLISION_CASES {
+            let mut kikan = test_kikan();
+            let ids: Vec<UnitId> = case
+                .units
+                .iter()
+                .map(|(start, _)| {
+                    let mut unit = Unit::builder();
+                    unit.set_engine(EngineType::STE);
+                    kikan.add_unit(*start, unit).unwrap()
+                })
+                .collect();
+            for id in case.wrecks {
+                kikan.damage_unit(*id, DEFAULT_HEALTH, None).unwrap();
+            }
+            for (id, (_, target)) in ids.iter().zip(case.units) {
+                if let Some(target) = target {
+                    kikan.commit_move(*id, *target);
+                }
+            }
+            kikan.apply_move();
+
+            let end: Vec<Position> = ids.iter().map(|id| kikan.get_unit_position(*id).unwrap()).collect();
+            assert_eq!(end, case.end, "{}", case.name);
+            let blocked: BTreeSet<UnitId> = kikan
+                .last_events()
+                .iter()
+                .filter_map(|event| match event {
+                    Event::Collision { unit, .. } => Some(*unit),
+                    _ => None,
+                })
+                .collect();
+            let expected: BTreeSet<UnitId> = case.blocked.iter().copied().collect();
+            assert_eq!(blocked, expected, "{}", case.name);
+        }
+    }
+
+    #[test]
+    fn collision_event() {
+        let mut kikan = test_kikan();
+        let mut unit0 = Unit::builder();
+        unit0.set_engine(EngineType::STE);
+        let mut unit1 = Unit::builder();
+        unit1.set_engine(EngineType::STE);
+        let u0 = kikan.add_unit(Position(0, 0), unit0).unwrap();
+        let u1 = kikan.add_unit(Position(1, 0), unit1).unwrap();
+
+        kikan.plan_unit_move(u0, Move::N).unwrap();
+        for _ in 0..10 {
+            kikan.update().unwrap();
+            assert!(kikan.last_events().is_empty());
+        }
+        kikan.update().unwrap();
+        assert_eq!(
+            kikan.last_events(),
+            &[Event::Collision {
+                unit: u0,
+                with: u1,
+                at: Position(1, 0)
+            }]
+        );
+        kikan.update().unwrap();
+        assert!(kikan.last_events().is_empty());
+    }
+
+    fn two_units(kikan: &mut Kikan, pos0: Position, pos1: Position) -> (UnitId, UnitId) {
+        let mut unit0 = Unit::builder();
+        unit0.set_engine(EngineType::STE);
+        let mut unit1 = Unit::builder();
+        unit1.set_engine(EngineType::STE);
+        (
+            kikan.add_unit(pos0, unit0).unwrap(),
+            kikan.add_unit(pos1, unit1).unwrap(),
+        )
+    }
+
+    #[test]
+    fn collision_damage() {
+        let mut kikan = test_kikan();
+        kikan.set_rules(Rules {
+            collision_damage: true,
+            ..Rules::default()
+        });
+        let (u0, u1) = two_units(&mut kikan, Position(0, 0), Position(2, 0));
+        kikan.commit_move(u0, Position(1, 0));
+        kikan.commit_move(u1, Position(1, 0));
+        kikan.apply_move();
+        let hit = EngineType::STE.mass() / 2;
+        // each one is blocked by the other, but it is one crash
+        assert_eq!(kikan.get_unit_health(u0), Some(DEFAULT_HEALTH - hit));
+        assert_eq!(kikan.get_unit_health(u1), Some(DEFAULT_HEALTH - hit));
+        assert!(kikan.take_unit_crash(u0).unwrap());
+        assert!(!kikan.take_unit_crash(u0).unwrap());
+
+        let mut kikan = test_kikan();
+        let (u0, u1) = two_units(&mut kikan, Position(0, 0), Position(2, 0));
+        kikan.commit_move(u0, Position(1, 0));
+        kikan.commit_move(u1, Position(1, 0));
+        kikan.apply_move();
+        assert_eq!(kikan.get_unit_health(u0), Some(DEFAULT_HEALTH));
+        assert!(kikan.take_unit_crash(u1).unwrap());
+    }
+
+    #[test]
+    fn pushing() {
+        let mut kikan = test_kikan();
+        kikan.set_rules(Rules {
+            pushing: true,
+            ..Rules::default()
+        });
+        let (u0, u1) = two_units(&mut kikan, Position(0, 0), Position(1, 0));
+        kikan.commit_move(u0, Position(1, 0));
+        kikan.apply_move();
+        assert_eq!(kikan.get_unit_position(u0), Some(Position(1, 0)));
+        assert_eq!(kikan.get_unit_position(u1), Some(Position(2, 0)));
+        assert_eq!(
+            kikan.last_events(),
+            &[
+                Event::Moved {
+                    unit: u1,
+                    from: Position(1, 0),
+                    to: Position(2, 0)
+                },
+                Event::Moved {
+                    unit: u0,
+                    from: Position(0, 0),
+                    to: Position(1, 0)
+                },
+                Event::Pushed {
+                    unit: u0,
+                    with: u1,
+                    to: Position(2, 0)
+                }
+            ]
+        );
+        assert!(kikan.take_unit_crash(u1).unwrap());
+
+        // nothing gets pushed where its engine can not go
+        kikan.map.set_terrain(Position(3, 0), Terrain::Water);
+        kikan.commit_move(u0, Position(2, 0));
+        kikan.apply_move();
+        assert_eq!(kikan.get_unit_position(u0), Some(Position(1, 0)));
+        assert_eq!(kikan.get_unit_position(u1), Some(Position(2, 0)));
+    }
+
+    #[test]
+    fn ramming() {
+        let mut kikan = test_kikan();
+        kikan.set_rules(Rules {
+            collision_damage: true,
+            pushing: true,
+            ..Rules::default()
+        });
+        let (u0, u1) = two_units(&mut kikan, Position(0, 0), Position(1, 0));
+        let mut wall = Unit::builder();
+        wall.set_engine(EngineType::STE);
+        let u2 = kikan.add_unit(Position(2, 0), wall).unwrap();
+        kikan.commit_move(u0, Position(1, 0));
+        kikan.apply_move();
+        // no room behind, so it is a crash
+        assert_eq!(kikan.get_unit_position(u0), Some(Position(0, 0)));
+        assert_eq!(kikan.get_unit_position(u1), Some(Position(1, 0)));
+        let hit = EngineType::STE.mass() / 2;
+        assert_eq!(kikan.get_unit_health(u0), Some(DEFAULT_HEALTH - hit));
+        assert_eq!(kikan.get_unit_health(u1), Some(DEFAULT_HEALTH - hit));
+        assert_eq!(kikan.get_unit_health(u2), Some(DEFAULT_HEALTH));
+        assert_eq!(kikan.get_unit(u0).unwrap().damage_dealt(), hit);
+    }
+
+    #[test]
+    fn walls() {
+        let mut kikan = test_kikan();
+        let mut map = Map::new(3, 3);
+        map.add_wall(Position(1, 1));
+        kikan.set_map(map);
+        assert!(matches!(
+            kikan.add_unit(Position(1, 1), Unit::builder()),
+            Err(KikanError::BlockedCell)
+        ));
+        assert!(matches!(
+            kikan.add_unit(Position(3, 0), Unit::builder()),
+            Err(KikanError::BlockedCell)
+        ));
+        let (u0, u1) = two_units(&mut kikan, Position(0, 1), Position(0, 0));
+        kikan.commit_move(u0, Position(1, 1));
+        kikan.commit_move(u1, Position(-1, 0));
+        kikan.apply_move();
+        assert_eq!(kikan.get_unit_position(u0), Some(Position(0, 1)));
+        assert_eq!(kikan.get_unit_position(u1), Some(Position(0, 0)));
+        assert_eq!(
+            kikan.last_events(),
+            &[
+                Event::WallCollision {
+                    unit: u0,
+                    at: Position(1, 1)
+                },
+                Event::WallCollision {
+                    unit: u1,
+                    at: Position(-1, 0)
+                },
+            ]
+        );
+        assert!(kikan.take_unit_crash(u0).unwrap());
+        assert!(kikan.take_unit_crash(u1).unwrap());
+        assert_eq!(kikan.get_unit_health(u0), Some(DEFAULT_HEALTH));
+    }
+
+    #[test]
+    fn wall_chain() {
+        let mut kikan = test_kikan();
+        kikan.set_rules(Rules {
+            collision_damage: true,
+            pushing: true,
+            ..Rules::default()
+        });
+        let mut map = Map::new(4, 1);
+        map.add_wall(Position(2, 0));
+        kikan.set_map(map);
+        let (u0, u1) = two_units(&mut kikan, Position(0, 0), Position(1, 0));
+        kikan.commit_move(u0, Position(1, 0));
+        kikan.commit_move(u1, Position(2, 0));
+        kikan.apply_move();
+        assert_eq!(kikan.get_unit_position(u0), Some(Position(0, 0)));
+        assert_eq!(kikan.get_unit_position(u1), Some(Position(1, 0)));
+        let hit = EngineType::STE.mass() / 2;
+        assert_eq!(kikan.get_unit_health(u1), Some(DEFAULT_HEALTH - 2 * hit));
+
+        // nothing gets pushed into a wall either
+        kikan.commit_move(u0, Position(1, 0));
+        kikan.apply_move();
+        assert_eq!(kikan.get_unit_position(u1), Some(Position(1, 0)));
+    }
+
+    #[test]
+    fn start_pos_on_map() {
+        let mut kikan = test_kikan();
+        let mut map = Map::new(2, 2);
+        map.add_wall(Position(0, 1)).add_wall(Position(1, 0));
+        kikan.set_map(map);
+        assert_eq!(kikan.gen_start_pos().unwrap(), Position(0, 0));
+        two_units(&mut kikan, Position(0, 0), Position(1, 1));
+        assert!(matches!(kikan.gen_start_pos(), Err(KikanError::NoRoomForUnits(1))));
+    }
+
+    /// updates until the unit stands on `pos`
+    fn ticks_to(kikan: &mut Kikan, unit: UnitId, pos: Position) -> usize {
+        (1..=100)
+            .find(|_| {
+                kikan.update().unwrap();
+                kikan.get_unit_position(unit) == Some(pos)
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn terrain() {
+        let mut kikan = test_kikan();
+        let mut map = Map::open();
+        map.set_terrain(Position(1, 0), Terrain::Road)
+            .set_terrain(Position(2, 0), Terrain::Mud)
+            .set_terrain(Position(0, 1), Terrain::Water);
+        kikan.set_map(map);
+        let mut unit = Unit::builder();
+        unit.set_engine(EngineType::STE);
+        let u0 = kikan.add_unit(Position(0, 0), unit).unwrap();
+
+        assert!(matches!(
+            kikan.plan_unit_move(u0, Move::E),
+            Err(KikanError::BlockedCell)
+        ));
+        kikan.plan_unit_move(u0, Move::N).unwrap();
+        assert_eq!(ticks_to(&mut kikan, u0, Position(1, 0)), 7);
+        kikan.plan_unit_move(u0, Move::N).unwrap();
+        assert_eq!(ticks_to(&mut kikan, u0, Position(2, 0)), 31);
+        kikan.plan_unit_move(u0, Move::E).unwrap();
+        assert_eq!(ticks_to(&mut kikan, u0, Position(2, 1)), 11);
+    }
+
+    #[test]
+    fn on_map() {
+        let file = MapFile::parse("spawns = [[1, 1]]\n---\n#.\n..").unwrap();
+        let kikan = Kikan::kikan_on_map(&file);
+        let mut kikan = kikan.lock().unwrap();
+        assert_eq!(kikan.gen_start_pos().unwrap(), Position(1, 1));
+        assert_eq!(kikan.gen_start_pos().unwrap(), Position(0, 0));
+        assert!(kikan.map().is_wall(Position(1, 0)));
+    }
+
+    #[test]
+    fn engines() {
+        let mut kikan = test_kikan();
+        let mut light = Unit::builder();
+        light.set_engine(EngineType::Light);
+        let mut heavy = Unit::builder();
+        heavy.set_engine(EngineType::Heavy);
+        let u0 = kikan.add_unit(Position(0, 0), light).unwrap();
+        let u1 = kikan.add_unit(Position(1, 0), heavy).unwrap();
+
+        kikan.damage_unit(u1, 10, None).unwrap();
+        assert_eq!(kikan.get_unit_health(u1), Some(DEFAULT_HEALTH - 5));
+
+        kikan.commit_move(u0, Position(1, 0));
+        kikan.apply_move();
+        assert!(kikan.take_unit_crash(u0).unwrap());
+        assert!(matches!(kikan.plan_unit_move(u0, Move::E), Err(KikanError::ModBusy)));
+        kikan.plan_unit_move(u1, Move::N).unwrap();
+        for _ in 0..EngineType::Light.spec().stall {
+            kikan.update().unwrap();
+        }
+        kikan.plan_unit_move(u0, Move::E).unwrap();
+    }
+
+    #[test]
+    fn budget() {
+        let mut kikan = test_kikan();
+        kikan.set_rules(Rules {
+            budget: Some(40),
+            ..Rules::default()
+        });
+        let mut unit = Unit::builder();
+        unit.set_engine(EngineType::Hover).add_mods(
+            UnitModContainter::KineticWeapon(Box::new(KineticWeapon::new(KineticWeaponType::Autocannon))),
+            "gun".to_string(),
+        );
+        assert_eq!(unit.score(), 40);
+        kikan.add_unit(Position(0, 0), unit).unwrap();
+
+        let mut unit = Unit::builder();
+        unit.set_engine(EngineType::Heavy).add_mods(
+            UnitModContainter::KineticWeapon(Box::new(KineticWeapon::new(KineticWeaponType::Autocannon))),
+            "gun".to_string(),
+        );
+        assert!(matches!(
+            kikan.add_unit(Position(1, 0), unit),
+            Err(KikanError::OverBudget(45, 40))
+        ));
+    }
+
+    #[test]
+    fn energy() {
+        let mut kikan = test_kikan();
+        let mut unit = Unit::builder();
+        unit.set_engine(EngineType::Heavy).set_energy(6, 1);
+        let u0 = kikan.add_unit(Position(0, 0), unit).unwrap();
+        kikan.plan_unit_move(u0, Move::N).unwrap();
+        assert_eq!(kikan.get_unit_energy(u0), Some(2));
+        ticks_to(&mut kikan, u0, Position(1, 0));
+        assert_eq!(kikan.get_unit_energy(u0), Some(6));
+
+        kikan.plan_unit_move(u0, Move::N).unwrap();
+        kikan.update().unwrap();
+        kikan.damage_unit(u0, 1000, None).unwrap();
+        kikan.update().unwrap();
+        // wrecks do not recharge
+        assert_eq!(kikan.get_unit_energy(u0), Some(3));
+
+        let mut unit = Unit::builder();
+        unit.set_engine(EngineType::Heavy).set_energy(3, 1);
+        let u1 = kikan.add_unit(Position(5, 5), unit).unwrap();
+        assert!(matches!(
+            kikan.plan_unit_move(u1, Move::N),
+            Err(KikanError::OutOfEnergy)
+        ));
+        assert!(!kikan.is_unit_moving(u1).unwrap());
+        assert_eq!(kikan.get_unit_energy(u1), Some(3));
+    }
+
+    #[test]
+    fn paths() {
+        let mut kikan = test_kikan();
+        let mut map = Map::new(4, 4);
+        map.add_wall(Position(1, 1));
+        kikan.set_map(map);
+        let (u0, _) = two_units(&mut kikan, Position(0, 0), Position(3, 3));
+
+        kikan
+            .plan_unit_path(u0, Route::Moves(vec![Move::N, Move::NE, Move::E]))
+            .unwrap();
+        assert!(kikan.is_unit_moving(u0).unwrap());
+        // each step is planned on the update the one before is done
+        assert_eq!(ticks_to(&mut kikan, u0, Position(2, 2)), (10 + 1) + (14 + 1) + (10 + 1));
+        for _ in 0..2 {
+            kikan.update().unwrap();
+        }
+        assert!(!kikan.is_unit_moving(u0).unwrap());
+        assert!(!kikan.take_unit_path_interrupted(u0).unwrap());
+
+        kikan.plan_unit_path(u0, Route::Moves(vec![Move::S])).unwrap();
+        ticks_to(&mut kikan, u0, Position(1, 2));
+        // the wall at (1, 1) ends the path at its first step
+        kikan.plan_unit_path(u0, Route::Moves(vec![Move::W, Move::W])).unwrap();
+        for _ in 0..11 {
+            kikan.update().unwrap();
+        }
+        assert_eq!(kikan.get_unit_position(u0), Some(Position(1, 2)));
+        assert!(kikan.last_events().contains(&Event::PathInterrupted {
+            unit: u0,
+            at: Position(1, 2)
+        }));
+        assert!(!kikan.is_unit_moving(u0).unwrap());
+        assert!(kikan.take_unit_path_interrupted(u0).unwrap());
+
+        kikan.plan_unit_path(u0, Route::To(Position(0, 0))).unwrap();
+        ticks_to(&mut kikan, u0, Position(0, 0));
+        assert!(matches!(
+            kikan.plan_unit_path(u0, Route::To(Position(1, 1))),
+            Err(KikanError::NoPath)
+        ));
+    }
+
+    #[test]
+    fn hex() {
+        let mut kikan = test_kikan();
+        kikan.set_topology(Topology::Hex);
+        let mut unit = Unit::builder();
+        unit.set_engine(EngineType::STE).add_mods(
+            UnitModContainter::KineticWeapon(Box::new(KineticWeapon::new(KineticWeaponType::Autocannon))),
+            "gun".to_string(),
+        );
+        let u0 = kikan.add_unit(Position(0, 0), unit).unwrap();
+
+        assert!(matches!(
+            kikan.plan_unit_move(u0, Move::NE),
+            Err(KikanError::InvalidMove)
+        ));
+        kikan.plan_unit_move(u0, Move::NW).unwrap();
+        assert_eq!(ticks_to(&mut kikan, u0, Position(1, -1)), 11);
+
+        // six cells away on squares, three on hexes
+        kikan
+            .unit_mod_action(u0, "gun".to_string(), UnitActionContainer::Pos(Position(4, -4)))
+            .unwrap();
+
+        kikan.plan_unit_path(u0, Route::To(Position(3, 1))).unwrap();
+        ticks_to(&mut kikan, u0, Position(3, 1));
+        assert!(!kikan.take_unit_path_interrupted(u0).unwrap());
+    }
+
     #[test]
     fn bus_buffer() {
         let mut kikan = test_kikan();
@@ -341,4 +1880,27 @@ mod tests {
             kikan.update().unwrap();
         }
     }
+
+    #[test]
+    fn stuck_listener() {
+        let kikan = Kikan::kikan_in_a_shell(|| Position(0, 0));
+        // never read, and left behind by a dropped handle
+        let mut stuck = kikan.lock().unwrap().wait_for_update();
+        let forgotten = kikan.lock().unwrap().wait_for_update();
+        let world = {
+            let kikan = Arc::clone(&kikan);
+            std::thread::spawn(move || {
+                for _ in 0..1000 {
+                    kikan.lock().unwrap().update().unwrap();
+                }
+            })
+        };
+        world.join().unwrap();
+        drop(forgotten);
+        assert_eq!(kikan.lock().unwrap().tick(), 1000);
+        // skipped to what is still kept
+        assert_eq!(stuck.recv().unwrap().tick, 1000 - 42 + 1);
+        assert_eq!(stuck.iter().take(41).last().unwrap().tick, 1000);
+        assert!(stuck.try_recv().is_none());
+    }
 }
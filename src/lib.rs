@@ -1,5 +1,15 @@
+pub mod arena;
 pub mod arsenal;
 pub mod error;
+pub mod event;
 pub mod handler;
 pub mod kikan;
+pub mod map;
+pub mod notify;
+pub mod remote;
+pub mod render;
+pub mod replay;
+pub mod scheduler;
 pub mod script;
+pub mod snapshot;
+pub mod topology;
@@ -1,3 +1,207 @@
+use clap::Parser;
+use kikan::{
+    arena::{Arena, ArenaConfig, ArenaResult, UnitReport},
+    error::{KResult, KikanError},
+    kikan::{Position, Rules},
+    map::file::MapFile,
+    render::Film,
+    replay::Replay,
+    script::{parse_lib, ScriptConfig},
+};
+use mlua::StdLib;
+use opt::{Load, Opt, Sub, Svg};
+use std::{
+    fs, io,
+    net::TcpListener,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+mod opt;
+mod watch;
+
+/// Sets up the match, the map name is printed on the way.
+fn arena(opt: Load) -> KResult<Arena> {
+    let extra_libs = opt
+        .allow_lib
+        .iter()
+        .try_fold(StdLib::NONE, |libs, name| Ok::<_, KikanError>(libs | parse_lib(name)?))?;
+    let file = opt.map.as_ref().map(MapFile::load).transpose()?;
+    if let Some(name) = file.as_ref().and_then(|file| file.info.name.as_deref()) {
+        println!("map {}", name);
+    }
+    let topology = match file.as_ref().and_then(|file| file.topology) {
+        Some(topology) => topology,
+        None => opt.topology.parse()?,
+    };
+    let (map, spawns) = match file {
+        Some(file) => (Some(file.map), file.spawns),
+        None => (None, Vec::new()),
+    };
+    let mut arena = Arena::new(ArenaConfig {
+        seed: opt.seed,
+        max_ticks: opt.ticks,
+        map,
+        spawns,
+        topology,
+        turn_budget: Some(opt.turn_budget),
+        script: ScriptConfig {
+            max_instructions: opt.max_instructions,
+            max_memory: opt.max_memory,
+            extra_libs,
+            ..ScriptConfig::default()
+        },
+        rules: Rules {
+            collision_damage: opt.collision_damage,
+            pushing: opt.pushing,
+            budget: opt.budget,
+        },
+        record: opt.record.map(PathBuf::from),
+        film: opt.svg.wanted(),
+        remote_timeout: (opt.remote_timeout > 0).then(|| Duration::from_millis(opt.remote_timeout)),
+        ..ArenaConfig::default()
+    });
+    for target in opt.targets {
+        let script = fs::read_to_string(&target)?;
+        arena.add_script(target, script);
+    }
+    if let Some(addr) = opt.listen {
+        let listener = TcpListener::bind(&addr)?;
+        println!("waiting for {} bots on {}", opt.remote, listener.local_addr()?);
+        for _ in 0..opt.remote {
+            let (stream, peer) = listener.accept()?;
+            println!("bot from {}", peer);
+            arena.add_remote(format!("remote {}", peer), stream);
+        }
+    }
+    Ok(arena)
+}
+
+fn report(result: &ArenaResult) {
+    for (name, e) in result.script_errors.iter() {
+        eprintln!("{}: {}", name, e);
+    }
+    for (tick, e) in result.update_errors.iter() {
+        eprintln!("tick {}: {}", tick, e);
+    }
+    if let Some(e) = &result.recording_failed {
+        eprintln!("replay cut short: {}", e);
+    }
+    println!("tick {}", result.ticks);
+    for unit in result.units.iter() {
+        print_unit(unit);
+    }
+    match (result.winner(), result.winning_team()) {
+        (Some(winner), _) => println!("winner: {}", winner.name.as_deref().unwrap_or("?")),
+        (None, Some(team)) => println!("winner: team {}", team),
+        (None, None) => println!("no winner"),
+    }
+}
+
+fn export(film: &Film, svg: &Svg) -> KResult<()> {
+    if let Some(path) = &svg.svg {
+        film.save_animated(path, Duration::from_millis(svg.frame_ms))?;
+    }
+    if let Some(dir) = &svg.svg_frames {
+        film.save_frames(dir)?;
+    }
+    Ok(())
+}
+
+fn load(opt: Load) -> KResult<()> {
+    let svg = opt.svg.clone();
+    let result = arena(opt)?.run()?;
+    report(&result);
+    if let Some(film) = &result.film {
+        export(film, &svg)?;
+    }
+    Ok(())
+}
+
+fn watch(opt: opt::Watch) -> KResult<()> {
+    let svg = opt.load.svg.clone();
+    let arena = arena(opt.load)?;
+    let result = watch::watch(arena, Duration::from_millis(opt.delay))?;
+    report(&result);
+    if let Some(film) = &result.film {
+        export(film, &svg)?;
+    }
+    Ok(())
+}
+
+fn render(opt: opt::Render) -> KResult<()> {
+    let film = Film::of_replay(&Replay::load(&opt.file)?)?;
+    let mut svg = opt.svg;
+    if !svg.wanted() {
+        svg.svg = Some(Path::new(&opt.file).with_extension("svg").display().to_string());
+    }
+    export(&film, &svg)
+}
+
+fn print_unit(unit: &UnitReport) {
+    let name = match &unit.team {
+        Some(team) => format!("{} [{}]", unit.name.as_deref().unwrap_or("?"), team),
+        None => unit.name.as_deref().unwrap_or("?").to_string(),
+    };
+    let Position(x, y) = unit.position;
+    if unit.health == 0 {
+        println!("#{} {} ({}, {}) destroyed, score {}", unit.id, name, x, y, unit.score);
+    } else {
+        println!(
+            "#{} {} ({}, {}) hp {}, score {}",
+            unit.id, name, x, y, unit.health, unit.score
+        );
+    }
+}
+
+fn replay(opt: opt::Replay) -> KResult<()> {
+    let replay = Replay::load(&opt.file)?;
+    let mut player = replay.player();
+    let mut input = String::new();
+    while let Some(tick) = player.step()? {
+        if tick.orders.is_empty() && tick.events.is_empty() {
+            continue;
+        }
+        println!("tick {}", tick.tick);
+        for order in tick.orders.iter() {
+            println!("  > {}", order);
+        }
+        for event in tick.events.iter() {
+            println!("  {}", event);
+        }
+        if opt.step {
+            input.clear();
+            if io::stdin().read_line(&mut input)? == 0 || input.trim() == "q" {
+                return Ok(());
+            }
+        }
+    }
+    let kikan = player.kikan();
+    println!("tick {}", kikan.tick());
+    for id in kikan.unit_ids() {
+        let unit = kikan.get_unit(id).ok_or(KikanError::GhostUnit)?;
+        print_unit(&UnitReport {
+            id,
+            name: unit.name().map(str::to_string),
+            team: unit.team().map(str::to_string),
+            position: unit.position(),
+            health: unit.health(),
+            score: unit.damage_dealt(),
+        });
+    }
+    Ok(())
+}
+
 fn main() {
-    println!("Hello, world!");
+    let opt = Opt::parse();
+    let res = match opt.cmd {
+        Sub::Load(opt) => load(opt),
+        Sub::Replay(opt) => replay(opt),
+        Sub::Watch(opt) => watch(opt),
+        Sub::Render(opt) => render(opt),
+    };
+    if let Err(e) = res {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
 }
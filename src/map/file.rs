@@ -0,0 +1,281 @@
+//! Arenas as text: a TOML header, a `---` line and an optional ASCII grid.
+//!
+//! ```text
+//! name = "crossing"
+//! blocked = [[2, 2]]
+//!
+//! [teams]
+//! red = [0]
+//! blue = [1]
+//! ---
+//! 1..~~..
+//! ..=====
+//! .,,#...
+//! 0..%%..
+//! ```
+//!
+//! The first grid row is the northern edge, so the last one is `x = 0`; columns count `y` from 0.
+//! `.` open, `#` wall, `,` rough, `%` mud, `=` road, `~` water, a digit is the spawn of that
+//! index on open ground. Spawns may also be listed in the header, they take the first indices.
+//! With `topology = "hex"` the same grid is read as axial hex coordinates.
+
+use super::{Map, Spawn, Terrain};
+use crate::{
+    error::{KResult, KikanError},
+    kikan::Position,
+    topology::Topology,
+};
+use serde::Deserialize;
+use std::{collections::BTreeMap, fs, path::Path};
+
+const SEPARATOR: &str = "---";
+/// Longest side of a map, larger ones are a typo rather than an arena.
+const MAX_SIDE: u32 = 1024;
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Header {
+    name: Option<String>,
+    author: Option<String>,
+    description: Option<String>,
+    width: Option<u32>,
+    height: Option<u32>,
+    /// "square" or "hex"
+    topology: Option<String>,
+    #[serde(default)]
+    blocked: Vec<(i32, i32)>,
+    #[serde(default)]
+    spawns: Vec<(i32, i32)>,
+    /// team name to spawn indices
+    #[serde(default)]
+    teams: BTreeMap<String, Vec<usize>>,
+}
+
+/// Things about a map that do not change the game.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MapInfo {
+    pub name: Option<String>,
+    pub author: Option<String>,
+    pub description: Option<String>,
+}
+
+/// A parsed and checked map file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MapFile {
+    pub info: MapInfo,
+    pub map: Map,
+    pub spawns: Vec<Spawn>,
+    /// `None` if the file leaves it to the match
+    pub topology: Option<Topology>,
+}
+
+impl MapFile {
+    pub fn load(path: impl AsRef<Path>) -> KResult<Self> {
+        fs::read_to_string(path)?.parse()
+    }
+
+    pub fn parse(text: &str) -> KResult<Self> {
+        let (header, grid) = match text.lines().position(|line| line.trim_end() == SEPARATOR) {
+            Some(at) => {
+                let lines: Vec<&str> = text.lines().collect();
+                (lines[..at].join("\n"), Some(lines[at + 1..].to_vec()))
+            }
+            None => (text.to_string(), None),
+        };
+        let header: Header = toml::from_str(&header).map_err(|e| KikanError::MapSyntax(e.to_string()))?;
+        let topology = header.topology.as_deref().map(str::parse).transpose()?;
+        let grid: Vec<&str> = grid
+            .unwrap_or_default()
+            .into_iter()
+            .map(str::trim_end)
+            .filter(|line| !line.is_empty())
+            .collect();
+
+        let grid_size = grid.first().map(|row| (grid.len() as u32, row.chars().count() as u32));
+        let (height, width) = match (header.height.zip(header.width), grid_size) {
+            (Some(size), Some(grid_size)) if size != grid_size => {
+                return Err(KikanError::MapSizeMismatch(size.0, size.1, grid_size.0, grid_size.1))
+            }
+            (Some(size), _) | (None, Some(size)) => size,
+            (None, None) => return Err(KikanError::MapSyntax("no size and no grid".to_string())),
+        };
+        if height == 0 || width == 0 || height > MAX_SIDE || width > MAX_SIDE {
+            return Err(KikanError::MapSyntax(format!(
+                "{} x {} is not between 1 and {} a side",
+                height, width, MAX_SIDE
+            )));
+        }
+
+        let mut map = Map::new(height, width);
+        let mut spawns: Vec<Option<Position>> = header.spawns.iter().map(|(x, y)| Some(Position(*x, *y))).collect();
+        for (row, line) in grid.iter().enumerate() {
+            if line.chars().count() as u32 != width {
+                return Err(KikanError::MapSizeMismatch(
+                    height,
+                    width,
+                    height,
+                    line.chars().count() as u32,
+                ));
+            }
+            let x = (height as usize - 1 - row) as i32;
+            for (y, c) in line.chars().enumerate() {
+                let pos = Position(x, y as i32);
+                let terrain = match c {
+                    '.' => Terrain::Open,
+                    ',' => Terrain::Rough,
+                    '%' => Terrain::Mud,
+                    '=' => Terrain::Road,
+                    '~' => Terrain::Water,
+                    '#' => {
+                        map.add_wall(pos);
+                        continue;
+                    }
+                    c if c.is_ascii_digit() => {
+                        let index = c as usize - '0' as usize;
+                        if spawns.len() <= index {
+                            spawns.resize(index + 1, None);
+                        }
+                        if spawns[index].replace(pos).is_some() {
+                            return Err(KikanError::DuplicateSpawn(index));
+                        }
+                        Terrain::Open
+                    }
+                    c => return Err(KikanError::BadMapCell(c, pos.0, pos.1)),
+                };
+                map.set_terrain(pos, terrain);
+            }
+        }
+        for (x, y) in header.blocked {
+            map.add_wall(Position(x, y));
+        }
+
+        let mut spawns = spawns
+            .into_iter()
+            .enumerate()
+            .map(|(i, pos)| match pos {
+                Some(pos) if map.is_free(pos) => Ok(Spawn { pos, team: None }),
+                Some(_) => Err(KikanError::BadSpawn(i)),
+                None => Err(KikanError::MapSyntax(format!("spawn {} is missing", i))),
+            })
+            .collect::<KResult<Vec<Spawn>>>()?;
+        for (team, indices) in header.teams {
+            for i in indices {
+                let spawn = spawns
+                    .get_mut(i)
+                    .ok_or_else(|| KikanError::UnknownSpawn(team.clone(), i))?;
+                if spawn.team.replace(team.clone()).is_some() {
+                    return Err(KikanError::DuplicateSpawn(i));
+                }
+            }
+        }
+
+        Ok(Self {
+            info: MapInfo {
+                name: header.name,
+                author: header.author,
+                description: header.description,
+            },
+            map,
+            spawns,
+            topology,
+        })
+    }
+}
+
+impl std::str::FromStr for MapFile {
+    type Err = KikanError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CROSSING: &str = r#"
+name = "crossing"
+blocked = [[2, 2]]
+
+[teams]
+red = [0]
+blue = [1]
+---
+1..~~..
+..=====
+.,,#...
+0..%%..
+"#;
+
+    #[test]
+    fn crossing() {
+        let file = MapFile::parse(CROSSING).unwrap();
+        assert_eq!(file.info.name.as_deref(), Some("crossing"));
+        assert_eq!(file.map.size(), Some((4, 7)));
+        assert!(file.map.is_wall(Position(1, 3)));
+        assert!(file.map.is_wall(Position(2, 2)));
+        assert_eq!(file.map.terrain(Position(3, 3)), Terrain::Water);
+        assert_eq!(file.map.terrain(Position(2, 6)), Terrain::Road);
+        assert_eq!(file.map.terrain(Position(0, 3)), Terrain::Mud);
+        assert_eq!(file.map.terrain(Position(1, 1)), Terrain::Rough);
+        assert_eq!(
+            file.spawns,
+            vec![
+                Spawn {
+                    pos: Position(0, 0),
+                    team: Some("red".to_string())
+                },
+                Spawn {
+                    pos: Position(3, 0),
+                    team: Some("blue".to_string())
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn header_only() {
+        let file = MapFile::parse("width = 3\nheight = 2\nspawns = [[0, 0], [1, 2]]\nblocked = [[0, 1]]").unwrap();
+        assert_eq!(file.map.size(), Some((2, 3)));
+        assert_eq!(file.topology, None);
+        assert_eq!(file.map.free_cells().len(), 5);
+        assert_eq!(file.spawns[1].pos, Position(1, 2));
+    }
+
+    #[test]
+    fn invalid() {
+        type Check = fn(&KikanError) -> bool;
+        let cases: &[(&str, Check)] = &[
+            ("name = ", |e| matches!(e, KikanError::MapSyntax(_))),
+            ("colour = 1\n---\n..", |e| matches!(e, KikanError::MapSyntax(_))),
+            ("", |e| matches!(e, KikanError::MapSyntax(_))),
+            ("width = 4294967295\nheight = 4294967295", |e| {
+                matches!(e, KikanError::MapSyntax(_))
+            }),
+            ("width = 0\nheight = 3", |e| matches!(e, KikanError::MapSyntax(_))),
+            ("width = 3\nheight = 1\n---\n..", |e| {
+                matches!(e, KikanError::MapSizeMismatch(1, 3, 1, 2))
+            }),
+            ("---\n...\n..", |e| matches!(e, KikanError::MapSizeMismatch(2, 3, 2, 2))),
+            ("---\n.x.", |e| matches!(e, KikanError::BadMapCell('x', 0, 1))),
+            ("topology = \"tri\"\n---\n.", |e| {
+                matches!(e, KikanError::NoSuchTopology(_))
+            }),
+            ("---\n0.0", |e| matches!(e, KikanError::DuplicateSpawn(0))),
+            ("spawns = [[0, 1]]\n---\n.#", |e| matches!(e, KikanError::BadSpawn(0))),
+            ("spawns = [[5, 5]]\n---\n..", |e| matches!(e, KikanError::BadSpawn(0))),
+            ("---\n.1", |e| matches!(e, KikanError::MapSyntax(_))),
+            ("[teams]\na = [1]\n---\n0.", |e| {
+                matches!(e, KikanError::UnknownSpawn(_, 1))
+            }),
+            ("[teams]\na = [0]\nb = [0]\n---\n0.", |e| {
+                matches!(e, KikanError::DuplicateSpawn(0))
+            }),
+        ];
+        for (text, check) in cases {
+            let e = MapFile::parse(text).unwrap_err();
+            assert!(check(&e), "{:?}: {:?}", text, e);
+        }
+    }
+}
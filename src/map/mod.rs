@@ -0,0 +1,142 @@
+use crate::{error::KikanError, kikan::Position};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    str::FromStr,
+};
+
+pub mod file;
+mod path;
+
+/// Where a unit enters the arena.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Spawn {
+    pub pos: Position,
+    pub team: Option<String>,
+}
+
+/// Ground of a cell, engines cross each kind at their own pace.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Terrain {
+    #[default]
+    Open,
+    Rough,
+    Mud,
+    Road,
+    Water,
+}
+
+impl Terrain {
+    pub const ALL: [Terrain; 5] = [Self::Open, Self::Rough, Self::Mud, Self::Road, Self::Water];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Open => "open",
+            Self::Rough => "rough",
+            Self::Mud => "mud",
+            Self::Road => "road",
+            Self::Water => "water",
+        }
+    }
+}
+
+impl FromStr for Terrain {
+    type Err = KikanError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "open" => Self::Open,
+            "rough" => Self::Rough,
+            "mud" => Self::Mud,
+            "road" => Self::Road,
+            "water" => Self::Water,
+            _ => return Err(KikanError::NoSuchTerrain(s.to_string())),
+        })
+    }
+}
+
+/// Edges and walls of the world.
+/// `height` runs along x (north), `width` along y (east), both from 0.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Map {
+    /// height and width, `None` for a world without edges
+    size: Option<(u32, u32)>,
+    walls: BTreeSet<Position>,
+    /// cells not listed here are open ground
+    terrain: BTreeMap<Position, Terrain>,
+}
+
+impl Map {
+    /// No edges and no walls.
+    pub fn open() -> Self {
+        Self::default()
+    }
+
+    pub fn new(height: u32, width: u32) -> Self {
+        Self {
+            size: Some((height, width)),
+            walls: BTreeSet::new(),
+            terrain: BTreeMap::new(),
+        }
+    }
+
+    pub fn add_wall(&mut self, pos: Position) -> &mut Self {
+        self.walls.insert(pos);
+        self
+    }
+
+    pub fn set_terrain(&mut self, pos: Position, terrain: Terrain) -> &mut Self {
+        if terrain == Terrain::Open {
+            self.terrain.remove(&pos);
+        } else {
+            self.terrain.insert(pos, terrain);
+        }
+        self
+    }
+
+    pub fn terrain(&self, pos: Position) -> Terrain {
+        self.terrain.get(&pos).copied().unwrap_or_default()
+    }
+
+    pub fn size(&self) -> Option<(u32, u32)> {
+        self.size
+    }
+
+    pub fn walls(&self) -> impl Iterator<Item = &Position> {
+        self.walls.iter()
+    }
+
+    /// Cells which are not open ground.
+    pub fn terrain_cells(&self) -> impl Iterator<Item = (&Position, &Terrain)> {
+        self.terrain.iter()
+    }
+
+    pub fn contains(&self, pos: Position) -> bool {
+        match self.size {
+            Some((height, width)) => pos.0 >= 0 && pos.1 >= 0 && (pos.0 as u32) < height && (pos.1 as u32) < width,
+            None => true,
+        }
+    }
+
+    pub fn is_wall(&self, pos: Position) -> bool {
+        self.walls.contains(&pos)
+    }
+
+    /// Units may stand here.
+    pub fn is_free(&self, pos: Position) -> bool {
+        self.contains(pos) && !self.is_wall(pos)
+    }
+
+    /// Every free cell in order, empty for a world without edges.
+    pub fn free_cells(&self) -> Vec<Position> {
+        let (height, width) = match self.size {
+            Some(size) => size,
+            None => return Vec::new(),
+        };
+        (0..height as i32)
+            .flat_map(|x| (0..width as i32).map(move |y| Position(x, y)))
+            .filter(|pos| !self.is_wall(*pos))
+            .collect()
+    }
+}
@@ -0,0 +1,133 @@
+use super::{Map, Terrain};
+use crate::{
+    arsenal::engine::{EngineType, Move, Step},
+    kikan::Position,
+    topology::Topology,
+};
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap},
+};
+
+/// cells around `from` and `to` searched on maps without edges
+const OPEN_MARGIN: i32 = 8;
+
+impl Map {
+    /// Cheapest moves from `from` to `to` for `engine`, `None` if it cannot get there.
+    /// Other units are not taken into account.
+    pub fn find_path(&self, from: Position, to: Position, engine: EngineType, topology: Topology) -> Option<Vec<Move>> {
+        if !self.is_free(to) {
+            return None;
+        }
+        let (low, high) = (
+            Position(from.0.min(to.0) - OPEN_MARGIN, from.1.min(to.1) - OPEN_MARGIN),
+            Position(from.0.max(to.0) + OPEN_MARGIN, from.1.max(to.1) + OPEN_MARGIN),
+        );
+        let searched = |pos: Position| {
+            self.is_free(pos)
+                && (self.size.is_some() || (low.0..=high.0).contains(&pos.0) && (low.1..=high.1).contains(&pos.1))
+        };
+        // no step is cheaper, so this never overestimates
+        let cheapest = Terrain::ALL.iter().filter_map(|t| engine.move_delay(*t)).min()?;
+        let estimate = |pos: Position| topology.steps(pos, to) * cheapest;
+
+        let mut cost: HashMap<Position, usize> = HashMap::from([(from, 0)]);
+        let mut came_from: HashMap<Position, (Position, Move)> = HashMap::new();
+        let mut open = BinaryHeap::from([Reverse((estimate(from), 0, from))]);
+        while let Some(Reverse((_, spent, pos))) = open.pop() {
+            if pos == to {
+                let mut moves = Vec::new();
+                let mut at = to;
+                while let Some((prev, m)) = came_from.get(&at) {
+                    moves.push(*m);
+                    at = *prev;
+                }
+                moves.reverse();
+                return Some(moves);
+            }
+            if cost.get(&pos).is_some_and(|best| *best < spent) {
+                continue;
+            }
+            for direction in topology.moves().iter().copied() {
+                let next = direction.next(pos);
+                if !searched(next) {
+                    continue;
+                }
+                let step = Step {
+                    direction,
+                    terrain: self.terrain(next),
+                    topology,
+                };
+                let delay = match engine.step_delay(step) {
+                    Some(delay) => delay,
+                    None => continue,
+                };
+                let spent = spent + delay;
+                if cost.get(&next).is_none_or(|best| spent < *best) {
+                    cost.insert(next, spent);
+                    came_from.insert(next, (pos, direction));
+                    open.push(Reverse((spent + estimate(next), spent, next)));
+                }
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn walk(from: Position, moves: &[Move]) -> Position {
+        moves.iter().fold(from, |pos, m| m.next(pos))
+    }
+
+    #[test]
+    fn around_walls() {
+        let mut map = Map::new(5, 5);
+        for y in 0..4 {
+            map.add_wall(Position(2, y));
+        }
+        let path = map
+            .find_path(Position(0, 0), Position(4, 0), EngineType::STE, Topology::Square)
+            .unwrap();
+        assert_eq!(walk(Position(0, 0), &path), Position(4, 0));
+        let mut pos = Position(0, 0);
+        for m in path.iter() {
+            pos = m.next(pos);
+            assert!(map.is_free(pos));
+        }
+        assert!(map
+            .find_path(Position(0, 0), Position(2, 0), EngineType::STE, Topology::Square)
+            .is_none());
+    }
+
+    #[test]
+    fn prefers_roads() {
+        let mut map = Map::open();
+        for y in -1..=4 {
+            map.set_terrain(Position(1, y), Terrain::Road);
+        }
+        for y in 0..=3 {
+            map.set_terrain(Position(0, y), Terrain::Mud);
+        }
+        let path = map
+            .find_path(Position(0, 0), Position(0, 3), EngineType::STE, Topology::Square)
+            .unwrap();
+        assert_eq!(walk(Position(0, 0), &path), Position(0, 3));
+        assert!(path.contains(&Move::NE) || path.contains(&Move::N));
+
+        // water is no way for a tracked unit, but a straight one for a hover
+        let mut map = Map::new(3, 3);
+        map.set_terrain(Position(1, 1), Terrain::Water);
+        let path = map
+            .find_path(Position(1, 0), Position(1, 2), EngineType::Hover, Topology::Square)
+            .unwrap();
+        assert_eq!(path, vec![Move::E, Move::E]);
+        let path = map
+            .find_path(Position(1, 0), Position(1, 2), EngineType::STE, Topology::Square)
+            .unwrap();
+        assert_eq!(path.len(), 2);
+        assert!(path.iter().all(|m| m.is_diagonal()));
+    }
+}
@@ -0,0 +1,143 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Condvar, Mutex},
+};
+
+struct Sent<T> {
+    /// the last messages, oldest first
+    history: VecDeque<T>,
+    /// messages sent so far
+    count: u64,
+    closed: bool,
+}
+
+struct Shared<T> {
+    sent: Mutex<Sent<T>>,
+    wake: Condvar,
+}
+
+/// Broadcasts to any number of listeners without ever waiting for them.
+/// The last `capacity` messages are kept, listeners falling further behind skip ahead.
+pub struct Notifier<T> {
+    shared: Arc<Shared<T>>,
+    capacity: usize,
+}
+
+impl<T: Clone> Notifier<T> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            shared: Arc::new(Shared {
+                sent: Mutex::new(Sent {
+                    history: VecDeque::with_capacity(capacity),
+                    count: 0,
+                    closed: false,
+                }),
+                wake: Condvar::new(),
+            }),
+            capacity: capacity.max(1),
+        }
+    }
+
+    pub fn send(&self, message: T) {
+        let mut sent = self.shared.sent.lock().unwrap();
+        if sent.history.len() == self.capacity {
+            sent.history.pop_front();
+        }
+        sent.history.push_back(message);
+        sent.count += 1;
+        self.shared.wake.notify_all();
+    }
+
+    /// A listener hearing every message sent from now on.
+    pub fn listen(&self) -> Listener<T> {
+        let next = self.shared.sent.lock().unwrap().count;
+        Listener {
+            shared: Arc::clone(&self.shared),
+            next,
+        }
+    }
+}
+
+impl<T> Drop for Notifier<T> {
+    fn drop(&mut self) {
+        self.shared.sent.lock().unwrap().closed = true;
+        self.shared.wake.notify_all();
+    }
+}
+
+pub struct Listener<T> {
+    shared: Arc<Shared<T>>,
+    /// number of the next message to hand out
+    next: u64,
+}
+
+impl<T: Clone> Listener<T> {
+    /// Waits for the next message, `None` once the notifier is gone and everything is read.
+    pub fn recv(&mut self) -> Option<T> {
+        let mut sent = self.shared.sent.lock().unwrap();
+        while self.next >= sent.count && !sent.closed {
+            sent = self.shared.wake.wait(sent).unwrap();
+        }
+        take(&sent, &mut self.next)
+    }
+
+    /// The next message if there is one already.
+    pub fn try_recv(&mut self) -> Option<T> {
+        let sent = self.shared.sent.lock().unwrap();
+        take(&sent, &mut self.next)
+    }
+
+    pub fn iter(&mut self) -> impl Iterator<Item = T> + '_ {
+        std::iter::from_fn(move || self.recv())
+    }
+
+    /// Messages sent but not read yet, dropped ones included.
+    pub fn behind(&self) -> u64 {
+        self.shared.sent.lock().unwrap().count - self.next
+    }
+}
+
+/// Hands out message `next`, or the oldest one kept if it is gone.
+fn take<T: Clone>(sent: &Sent<T>, next: &mut u64) -> Option<T> {
+    if *next >= sent.count {
+        return None;
+    }
+    let oldest = sent.count - sent.history.len() as u64;
+    *next = (*next).max(oldest);
+    let message = sent.history[(*next - oldest) as usize].clone();
+    *next += 1;
+    Some(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn lagging() {
+        let notifier = Notifier::new(3);
+        let mut early = notifier.listen();
+        notifier.send(0);
+        let mut late = notifier.listen();
+        for i in 1..10 {
+            notifier.send(i);
+        }
+        assert_eq!(early.behind(), 10);
+        assert_eq!(early.iter().take(3).collect::<Vec<_>>(), vec![7, 8, 9]);
+        assert_eq!(early.try_recv(), None);
+        assert_eq!(late.recv(), Some(7));
+        drop(notifier);
+        assert_eq!(late.iter().collect::<Vec<_>>(), vec![8, 9]);
+    }
+
+    #[test]
+    fn wakes_listeners() {
+        let notifier = Notifier::new(1);
+        let mut listener = notifier.listen();
+        let waiting = thread::spawn(move || listener.iter().collect::<Vec<_>>());
+        notifier.send("tick");
+        drop(notifier);
+        assert_eq!(waiting.join().unwrap(), vec!["tick"]);
+    }
+}
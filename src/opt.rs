@@ -1,17 +1,118 @@
-use clap::Clap;
+use clap::{Args, Parser, Subcommand};
 
-#[derive(Clap, Debug, Clone)]
+#[derive(Parser, Debug, Clone)]
 pub(crate) struct Opt {
     #[clap(subcommand)]
-    cmd: Sub,
+    pub cmd: Sub,
 }
 
-#[derive(Debug, Clone, Clap)]
+#[derive(Debug, Clone, Subcommand)]
 pub(crate) enum Sub {
+    /// Run a match between lua unit scripts
     Load(Load),
+    /// Play a recorded match again and check it goes the same way
+    Replay(Replay),
+    /// Run a match and show it live in the terminal
+    Watch(Watch),
+    /// Draw a recorded match as svg
+    Render(Render),
 }
 
-#[derive(Debug, Clone, Clap)]
+#[derive(Debug, Clone, Args)]
 pub(crate) struct Load {
-    target: String,
+    /// lua scripts, one unit each
+    #[clap(required_unless_present = "listen")]
+    pub targets: Vec<String>,
+    /// stop after this many ticks
+    #[clap(long, default_value = "1000")]
+    pub ticks: usize,
+    /// seed for start positions
+    #[clap(long, default_value = "0")]
+    pub seed: u64,
+    /// map file, scripts start on its spawns in order
+    #[clap(long)]
+    pub map: Option<String>,
+    /// square or hex, a map file may pick its own
+    #[clap(long, default_value = "square")]
+    pub topology: String,
+    /// lua instructions a script may run per tick
+    #[clap(long, default_value = "100000")]
+    pub turn_budget: u32,
+    /// lua instructions a script may run in the whole match
+    #[clap(long)]
+    pub max_instructions: Option<u64>,
+    /// bytes of memory a script may use
+    #[clap(long)]
+    pub max_memory: Option<usize>,
+    /// lua library scripts may use on top of string, table and math
+    #[clap(long)]
+    pub allow_lib: Vec<String>,
+    /// colliding units damage each other
+    #[clap(long)]
+    pub collision_damage: bool,
+    /// units can push standing units away
+    #[clap(long)]
+    pub pushing: bool,
+    /// points each unit may spend on its engine and mods
+    #[clap(long)]
+    pub budget: Option<u32>,
+    /// write a replay of the match to this file
+    #[clap(long)]
+    pub record: Option<String>,
+    #[clap(flatten)]
+    pub svg: Svg,
+    /// wait on this address for bots speaking json lines, they play after the scripts
+    #[clap(long)]
+    pub listen: Option<String>,
+    /// number of bots to wait for
+    #[clap(long, default_value = "1")]
+    pub remote: usize,
+    /// milliseconds a bot may think before it is dropped, 0 to wait forever
+    #[clap(long, default_value = "5000")]
+    pub remote_timeout: u64,
+}
+
+#[derive(Debug, Clone, Args)]
+pub(crate) struct Svg {
+    /// write an animated svg of the match to this file
+    #[clap(long)]
+    pub svg: Option<String>,
+    /// write an svg for every tick into this directory
+    #[clap(long)]
+    pub svg_frames: Option<String>,
+    /// milliseconds a tick is shown in the animated svg
+    #[clap(long, default_value = "100")]
+    pub frame_ms: u64,
+}
+
+impl Svg {
+    pub fn wanted(&self) -> bool {
+        self.svg.is_some() || self.svg_frames.is_some()
+    }
+}
+
+#[derive(Debug, Clone, Args)]
+pub(crate) struct Watch {
+    #[clap(flatten)]
+    pub load: Load,
+    /// milliseconds between ticks at the start
+    #[clap(long, default_value = "100")]
+    pub delay: u64,
+}
+
+#[derive(Debug, Clone, Args)]
+pub(crate) struct Replay {
+    /// replay file written by `load --record`
+    pub file: String,
+    /// wait for enter after every tick where something happened, `q` stops
+    #[clap(long)]
+    pub step: bool,
+}
+
+#[derive(Debug, Clone, Args)]
+pub(crate) struct Render {
+    /// replay file written by `load --record`, drawn next to it as .svg unless told otherwise
+    pub file: String,
+    #[clap(flatten)]
+    pub svg: Svg,
 }
@@ -0,0 +1,249 @@
+//! Bots in any language, playing a unit over TCP.
+//!
+//! Every line a bot sends is one JSON call, named by `call`, and gets one JSON line back:
+//! `{"ok": value}`, with `null` for calls without a result, or `{"error": "message"}`.
+//!
+//! ```text
+//! > {"call": "set_engine", "engine": "light"}
+//! < {"ok":null}
+//! > {"call": "add_mod", "kind": "autocannon", "id": "gun"}
+//! > {"call": "ready"}
+//! > {"call": "get_position"}
+//! < {"ok":{"x":3,"y":1}}
+//! > {"call": "plan_move", "direction": "ne"}
+//! > {"call": "plan_path", "to": {"x": 7, "y": 7}}
+//! > {"call": "plan_path", "moves": ["n", "n", "e"]}
+//! > {"call": "mod_action", "id": "gun", "target": {"x": 5, "y": 2}}
+//! > {"call": "wait_for_update"}
+//! ```
+//!
+//! The other calls are `is_moving`, `path_interrupted`, `crashed`, `terrain` with `at`,
+//! `topology`, `energy`, `last_events` and `budget_remaining`, as on [`UnitHandler`].
+//! Like a script, a bot has the turn until it calls `wait_for_update`, the reply comes with
+//! the next turn. Once the match is over the reply is an error and the connection is closed.
+
+use crate::{
+    arsenal::{engine::Route, UnitActionContainer},
+    error::{KResult, KikanError},
+    handler::UnitHandler,
+    kikan::{Move, Position},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    io::{BufRead, BufReader, BufWriter, ErrorKind, Read, Write},
+    net::TcpStream,
+};
+
+/// One line from a bot.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "call", rename_all = "snake_case")]
+pub enum Call {
+    SetEngine {
+        engine: String,
+    },
+    AddMod {
+        kind: String,
+        id: String,
+    },
+    Ready,
+    GetPosition,
+    PlanMove {
+        direction: String,
+    },
+    /// `moves` or `to`, not both
+    PlanPath {
+        #[serde(default)]
+        moves: Option<Vec<String>>,
+        #[serde(default)]
+        to: Option<Position>,
+    },
+    PathInterrupted,
+    IsMoving,
+    Crashed,
+    Terrain {
+        at: Position,
+    },
+    Topology,
+    Energy,
+    LastEvents,
+    BudgetRemaining,
+    ModAction {
+        id: String,
+        target: Position,
+    },
+    WaitForUpdate,
+}
+
+/// One line back to a bot.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Reply {
+    Ok(Value),
+    Error(String),
+}
+
+impl From<KResult<Value>> for Reply {
+    fn from(res: KResult<Value>) -> Self {
+        match res {
+            Ok(value) => Self::Ok(value),
+            Err(e) => Self::Error(e.to_string()),
+        }
+    }
+}
+
+fn json<T: Serialize>(value: T) -> Value {
+    serde_json::to_value(value).expect("Replies are plain data")
+}
+
+fn direction(name: &str) -> KResult<Move> {
+    name.parse()
+        .map_err(|_| KikanError::WrongUnitArgs(format!("no move `{}`", name)))
+}
+
+impl Call {
+    pub fn apply<H: UnitHandler>(self, handler: &mut H) -> KResult<Value> {
+        Ok(match self {
+            Self::SetEngine { engine } => json(handler.set_engine(engine.parse()?)?),
+            Self::AddMod { kind, id } => json(handler.add_mod(kind.parse()?, id)?),
+            Self::Ready => json(handler.ready()?),
+            Self::GetPosition => json(handler.get_position()?),
+            Self::PlanMove { direction: name } => json(handler.plan_move(direction(&name)?)?),
+            Self::PlanPath { moves, to } => {
+                let route = match (moves, to) {
+                    (Some(moves), None) => {
+                        Route::Moves(moves.iter().map(|name| direction(name)).collect::<KResult<_>>()?)
+                    }
+                    (None, Some(to)) => Route::To(to),
+                    _ => return Err(KikanError::WrongUnitArgs("a path needs `moves` or `to`".to_string())),
+                };
+                json(handler.plan_path(route)?)
+            }
+            Self::PathInterrupted => json(handler.path_interrupted()?),
+            Self::IsMoving => json(handler.is_moving()?),
+            Self::Crashed => json(handler.crashed()?),
+            Self::Terrain { at } => json(handler.terrain(at)?),
+            Self::Topology => json(handler.topology()?),
+            Self::Energy => json(handler.energy()?),
+            Self::LastEvents => json(handler.last_events()?),
+            Self::BudgetRemaining => json(handler.budget_remaining()?),
+            Self::ModAction { id, target } => json(handler.mod_action(id, UnitActionContainer::Pos(target))?),
+            Self::WaitForUpdate => json(handler.wait_for_update()?),
+        })
+    }
+}
+
+/// Timeouts on the connection mean the bot stopped talking or listening.
+fn silence(e: std::io::Error) -> KikanError {
+    match e.kind() {
+        ErrorKind::WouldBlock | ErrorKind::TimedOut => KikanError::RemoteTimeout,
+        _ => e.into(),
+    }
+}
+
+/// Longest line a bot may send, far more than any call needs.
+pub const MAX_LINE: u64 = 64 * 1024;
+
+/// Answers calls from `input` until the bot hangs up or the match is over.
+/// A line longer than [`MAX_LINE`] gets an error back and ends the connection.
+pub fn serve<H: UnitHandler, R: BufRead, W: Write>(mut handler: H, mut input: R, mut output: W) -> KResult<()> {
+    let mut line = String::new();
+    loop {
+        line.clear();
+        let read = (&mut input).take(MAX_LINE + 1).read_line(&mut line).map_err(silence)?;
+        if read == 0 {
+            break;
+        }
+        if read as u64 > MAX_LINE && !line.ends_with('\n') {
+            let e = KikanError::RemoteSyntax(format!("line longer than {} bytes", MAX_LINE));
+            let reply = serde_json::to_string(&Reply::Error(e.to_string())).expect("Replies are plain data");
+            writeln!(output, "{}", reply).map_err(silence)?;
+            output.flush().map_err(silence)?;
+            return Err(e);
+        }
+        if line.trim().is_empty() {
+            continue;
+        }
+        let res = serde_json::from_str::<Call>(&line)
+            .map_err(|e| KikanError::RemoteSyntax(e.to_string()))
+            .and_then(|call| call.apply(&mut handler));
+        let over = matches!(res, Err(KikanError::MatchOver));
+        let reply = serde_json::to_string(&Reply::from(res)).expect("Replies are plain data");
+        writeln!(output, "{}", reply).map_err(silence)?;
+        output.flush().map_err(silence)?;
+        if over {
+            return Err(KikanError::MatchOver);
+        }
+    }
+    Ok(())
+}
+
+/// [`serve`] over a connection.
+pub fn serve_tcp<H: UnitHandler>(handler: H, stream: TcpStream) -> KResult<()> {
+    let input = BufReader::new(stream.try_clone()?);
+    serve(handler, input, BufWriter::new(stream))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{handler::LocalHandle, kikan::Kikan};
+    use std::io::Cursor;
+
+    fn talk(calls: &str) -> Vec<Reply> {
+        let kikan = Kikan::kikan_in_a_shell(|| Position(2, 2));
+        let mut output = Vec::new();
+        serve(LocalHandle::new(kikan), Cursor::new(calls), &mut output).unwrap();
+        String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn calls() {
+        let replies = talk(
+            r#"{"call": "set_engine", "engine": "light"}
+            {"call": "add_mod", "kind": "autocannon", "id": "gun"}
+
+            {"call": "get_position"}
+            {"call": "ready"}
+            {"call": "get_position"}
+            {"call": "plan_move", "direction": "ne"}
+            {"call": "is_moving"}
+            {"call": "terrain", "at": {"x": 0, "y": 0}}
+            {"call": "plan_path"}
+            {"call": "fly"}
+            {"call": "plan_move", "direction": "up"}"#,
+        );
+        assert_eq!(replies.len(), 11);
+        assert_eq!(replies[0], Reply::Ok(Value::Null));
+        assert_eq!(replies[2], Reply::Error(KikanError::Uninited.to_string()));
+        assert_eq!(replies[4], Reply::Ok(serde_json::json!({"x": 2, "y": 2})));
+        assert_eq!(replies[6], Reply::Ok(Value::Bool(true)));
+        assert_eq!(replies[7], Reply::Ok(Value::String("open".to_string())));
+        assert!(matches!(&replies[8], Reply::Error(e) if e.contains("`moves` or `to`")));
+        assert!(matches!(&replies[9], Reply::Error(e) if e.starts_with("Malformed call")));
+        assert!(matches!(&replies[10], Reply::Error(e) if e.contains("no move `up`")));
+    }
+
+    #[test]
+    fn long_line() {
+        let kikan = Kikan::kikan_in_a_shell(|| Position(2, 2));
+        let calls = format!(
+            "{{\"call\": \"ready\"}}\n{}\n{{\"call\": \"ready\"}}\n",
+            " ".repeat(MAX_LINE as usize + 1)
+        );
+        let mut output = Vec::new();
+        let res = serve(LocalHandle::new(kikan), Cursor::new(calls), &mut output);
+        assert!(matches!(res, Err(KikanError::RemoteSyntax(_))));
+        let replies: Vec<Reply> = String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(replies.len(), 2);
+        assert!(matches!(&replies[1], Reply::Error(e) if e.contains("longer than")));
+    }
+}
@@ -0,0 +1,554 @@
+use crate::{
+    error::KResult,
+    event::Event,
+    kikan::{Kikan, Position, UnitId},
+    map::Terrain,
+    replay::{Replay, World},
+    topology::Topology,
+};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::{self, Write},
+    fs,
+    path::Path,
+    time::Duration,
+};
+
+/// pixels per cell
+const CELL: f32 = 24.0;
+/// room for the tick above the grid
+const HEADER: f32 = 24.0;
+const TEAM_COLOURS: [&str; 6] = ["#d03a2f", "#2f6fd0", "#2e9e44", "#d0a62f", "#9b3fc4", "#2aa6a6"];
+const WRECK: &str = "#777777";
+const FIRE: &str = "#ff8c1a";
+
+/// A unit in one still.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mark {
+    pub id: UnitId,
+    pub pos: Position,
+    pub team: Option<String>,
+    pub alive: bool,
+}
+
+/// The world after one update.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Still {
+    pub tick: u64,
+    pub units: Vec<Mark>,
+    /// from the shooter to the target
+    pub shots: Vec<(Position, Position)>,
+    pub hit: Vec<UnitId>,
+}
+
+/// A match kept update by update, drawn as SVG frames or one animated SVG.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Film {
+    world: World,
+    stills: Vec<Still>,
+}
+
+impl Film {
+    /// Starts filming once the map and topology of `kikan` are set.
+    pub fn new(kikan: &Kikan) -> Self {
+        Self {
+            world: World::of(kikan),
+            stills: Vec::new(),
+        }
+    }
+
+    /// Plays a recorded match again, a still per update.
+    pub fn of_replay(replay: &Replay) -> KResult<Self> {
+        let mut player = replay.player();
+        let mut film = Self::new(player.kikan());
+        while player.step()?.is_some() {
+            film.shoot(player.kikan());
+        }
+        Ok(film)
+    }
+
+    pub fn stills(&self) -> &[Still] {
+        &self.stills
+    }
+
+    /// Keeps the world as the last update left it.
+    pub fn shoot(&mut self, kikan: &Kikan) {
+        let units: Vec<Mark> = kikan
+            .unit_ids()
+            .into_iter()
+            .filter_map(|id| kikan.get_unit(id).map(|unit| (id, unit)))
+            .map(|(id, unit)| Mark {
+                id,
+                pos: unit.position(),
+                team: unit.team().map(String::from),
+                alive: kikan.is_unit_alive(id),
+            })
+            .collect();
+        let mut shots = Vec::new();
+        let mut hit = Vec::new();
+        for event in kikan.last_events() {
+            match event {
+                Event::ShotFired { unit, target, .. } => {
+                    if let Some(shooter) = units.iter().find(|mark| mark.id == *unit) {
+                        shots.push((shooter.pos, *target));
+                    }
+                }
+                Event::Hit { unit, .. } if !hit.contains(unit) => hit.push(*unit),
+                _ => {}
+            }
+        }
+        self.stills.push(Still {
+            tick: kikan.tick(),
+            units,
+            shots,
+            hit,
+        });
+    }
+
+    /// One SVG per still, trails up to that still.
+    pub fn frames(&self) -> Vec<String> {
+        let canvas = Canvas::new(self);
+        (0..self.stills.len())
+            .map(|i| {
+                let mut out = String::new();
+                canvas.frame(&mut out, i).expect("Writing to a string can not fail");
+                out
+            })
+            .collect()
+    }
+
+    /// All stills in one SVG, `frame` apart and looping.
+    pub fn animated(&self, frame: Duration) -> String {
+        let mut out = String::new();
+        Canvas::new(self)
+            .animated(&mut out, frame)
+            .expect("Writing to a string can not fail");
+        out
+    }
+
+    pub fn save_animated<P: AsRef<Path>>(&self, path: P, frame: Duration) -> KResult<()> {
+        fs::write(path, self.animated(frame))?;
+        Ok(())
+    }
+
+    /// Writes `tick_00001.svg` and on into `dir`, which is created if needed.
+    pub fn save_frames<P: AsRef<Path>>(&self, dir: P) -> KResult<()> {
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir)?;
+        for (still, svg) in self.stills.iter().zip(self.frames()) {
+            fs::write(dir.join(format!("tick_{:05}.svg", still.tick)), svg)?;
+        }
+        Ok(())
+    }
+}
+
+/// Column and row of a cell with north up, hex rows shift half a cell each.
+fn grid(hex: bool, pos: Position) -> (f32, f32) {
+    let shift = if hex { pos.0 as f32 / 2.0 } else { 0.0 };
+    (pos.1 as f32 + shift, pos.0 as f32)
+}
+
+/// Values `attribute` takes from the frame each step starts on, the first one from the start.
+fn animate(out: &mut String, head: &str, steps: &[(usize, String)], frames: usize, dur: f32) -> fmt::Result {
+    let values: Vec<&str> = steps.iter().map(|(_, value)| value.as_str()).collect();
+    let times: Vec<String> = steps
+        .iter()
+        .enumerate()
+        .map(|(i, (frame, _))| match i {
+            0 => "0".to_string(),
+            _ => format!("{:.5}", *frame as f32 / frames as f32),
+        })
+        .collect();
+    write!(
+        out,
+        r#"<{} values="{}" keyTimes="{}" calcMode="discrete" dur="{:.3}s" repeatCount="indefinite"/>"#,
+        head,
+        values.join(";"),
+        times.join(";"),
+        dur
+    )
+}
+
+/// Opacity steps showing an element for frames `from..to`.
+fn shown(from: usize, to: usize, frames: usize) -> Vec<(usize, String)> {
+    let mut steps = Vec::new();
+    if from > 0 {
+        steps.push((0, "0".to_string()));
+    }
+    steps.push((from, "1".to_string()));
+    if to < frames {
+        steps.push((to, "0".to_string()));
+    }
+    steps
+}
+
+struct Canvas<'a> {
+    film: &'a Film,
+    hex: bool,
+    /// leftmost column and topmost row in view
+    left: f32,
+    top: f32,
+    width: f32,
+    height: f32,
+    colours: BTreeMap<UnitId, &'static str>,
+    /// where each unit went, by the still it got there in
+    trails: BTreeMap<UnitId, Vec<(usize, Position)>>,
+}
+
+impl<'a> Canvas<'a> {
+    fn new(film: &'a Film) -> Self {
+        let hex = film.world.topology == Topology::Hex;
+        let cells: Vec<Position> = match film.world.size {
+            Some((height, width)) => {
+                let (high, wide) = (height as i32 - 1, width as i32 - 1);
+                vec![
+                    Position(0, 0),
+                    Position(high, 0),
+                    Position(0, wide),
+                    Position(high, wide),
+                ]
+            }
+            None => film
+                .stills
+                .iter()
+                .flat_map(|still| {
+                    let shots = still.shots.iter().map(|shot| shot.1);
+                    still.units.iter().map(|mark| mark.pos).chain(shots)
+                })
+                .chain(film.world.walls.iter().copied())
+                .collect(),
+        };
+        let points: Vec<(f32, f32)> = cells.into_iter().map(|pos| grid(hex, pos)).collect();
+        let low_col = points.iter().map(|p| p.0).fold(f32::MAX, f32::min);
+        let high_col = points.iter().map(|p| p.0).fold(f32::MIN, f32::max);
+        let low_row = points.iter().map(|p| p.1).fold(f32::MAX, f32::min);
+        let high_row = points.iter().map(|p| p.1).fold(f32::MIN, f32::max);
+        let ((low_col, high_col), (low_row, high_row)) = match points.is_empty() {
+            true => ((0.0, 0.0), (0.0, 0.0)),
+            false => ((low_col, high_col), (low_row, high_row)),
+        };
+        // open maps get a cell of room around everything
+        let margin = if film.world.size.is_some() { 0.5 } else { 1.5 };
+
+        let teams: BTreeSet<&str> = film
+            .stills
+            .iter()
+            .flat_map(|still| still.units.iter())
+            .filter_map(|mark| mark.team.as_deref())
+            .collect();
+        let mut colours = BTreeMap::new();
+        let mut trails: BTreeMap<UnitId, Vec<(usize, Position)>> = BTreeMap::new();
+        for (i, still) in film.stills.iter().enumerate() {
+            for mark in still.units.iter() {
+                colours.entry(mark.id).or_insert_with(|| {
+                    let i = match mark.team.as_deref() {
+                        Some(team) => teams.iter().position(|t| *t == team).unwrap_or(0),
+                        None => teams.len() + mark.id as usize,
+                    };
+                    TEAM_COLOURS[i % TEAM_COLOURS.len()]
+                });
+                let trail = trails.entry(mark.id).or_default();
+                if trail.last().is_none_or(|(_, pos)| *pos != mark.pos) {
+                    trail.push((i, mark.pos));
+                }
+            }
+        }
+        Self {
+            film,
+            hex,
+            left: low_col - margin,
+            top: high_row + margin,
+            width: (high_col - low_col + 2.0 * margin) * CELL,
+            height: HEADER + (high_row - low_row + 2.0 * margin) * CELL,
+            colours,
+            trails,
+        }
+    }
+
+    /// Centre of a cell in pixels.
+    fn centre(&self, pos: Position) -> (f32, f32) {
+        let (col, row) = grid(self.hex, pos);
+        ((col - self.left) * CELL, HEADER + (self.top - row) * CELL)
+    }
+
+    fn colour(&self, id: UnitId) -> &'static str {
+        self.colours.get(&id).copied().unwrap_or(TEAM_COLOURS[0])
+    }
+
+    fn open(&self, out: &mut String) -> fmt::Result {
+        write!(
+            out,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}" font-family="monospace">"#,
+            w = self.width,
+            h = self.height
+        )?;
+        write!(out, r##"<rect width="100%" height="100%" fill="#f4f1ea"/>"##)?;
+        let world = &self.film.world;
+        let terrain: BTreeMap<Position, Terrain> = world.terrain.iter().copied().collect();
+        let walls: BTreeSet<Position> = world.walls.iter().copied().collect();
+        let mut cells: BTreeSet<Position> = terrain.keys().chain(walls.iter()).copied().collect();
+        if let Some((height, width)) = world.size {
+            cells.extend((0..height as i32).flat_map(|x| (0..width as i32).map(move |y| Position(x, y))));
+        }
+        for pos in cells {
+            let fill = if walls.contains(&pos) {
+                "#3a3a3a"
+            } else {
+                match terrain.get(&pos).copied().unwrap_or_default() {
+                    Terrain::Open => "#fbfaf6",
+                    Terrain::Rough => "#cdbd9c",
+                    Terrain::Mud => "#8b6b4a",
+                    Terrain::Road => "#a9a9a9",
+                    Terrain::Water => "#6aaed6",
+                }
+            };
+            let (x, y) = self.centre(pos);
+            if self.hex {
+                write!(
+                    out,
+                    r##"<circle cx="{}" cy="{}" r="{}" fill="{}" stroke="#e0dccf"/>"##,
+                    x,
+                    y,
+                    CELL / 2.0,
+                    fill
+                )?;
+            } else {
+                write!(
+                    out,
+                    r##"<rect x="{}" y="{}" width="{c}" height="{c}" fill="{}" stroke="#e0dccf"/>"##,
+                    x - CELL / 2.0,
+                    y - CELL / 2.0,
+                    fill,
+                    c = CELL
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    fn trail(&self, out: &mut String, id: UnitId, points: &[(usize, Position)]) -> fmt::Result {
+        let points: Vec<String> = points
+            .iter()
+            .map(|(_, pos)| {
+                let (x, y) = self.centre(*pos);
+                format!("{},{}", x, y)
+            })
+            .collect();
+        write!(
+            out,
+            r#"<polyline points="{}" fill="none" stroke="{}" stroke-opacity="0.4" stroke-width="3"/>"#,
+            points.join(" "),
+            self.colour(id)
+        )
+    }
+
+    fn shot(&self, out: &mut String, (from, to): (Position, Position), anim: &str) -> fmt::Result {
+        let ((x1, y1), (x2, y2)) = (self.centre(from), self.centre(to));
+        write!(
+            out,
+            r#"<g stroke="{f}"><line x1="{}" y1="{}" x2="{}" y2="{}" stroke-width="2"/><circle cx="{}" cy="{}" r="{}" fill="{f}"/>{}</g>"#,
+            x1,
+            y1,
+            x2,
+            y2,
+            x2,
+            y2,
+            CELL / 6.0,
+            anim,
+            f = FIRE
+        )
+    }
+
+    /// A unit drawn around the origin, to be moved into place.
+    fn unit(&self, out: &mut String, id: UnitId, fill: &str, anim: &str) -> fmt::Result {
+        write!(
+            out,
+            r##"<circle r="{}" fill="{}">{}</circle><text y="4" text-anchor="middle" font-size="11" fill="#fff">{}</text>"##,
+            CELL * 0.38,
+            fill,
+            anim,
+            id
+        )
+    }
+
+    fn hit(&self, out: &mut String, pos: Position, anim: &str) -> fmt::Result {
+        let (x, y) = self.centre(pos);
+        write!(
+            out,
+            r#"<circle cx="{}" cy="{}" r="{}" fill="none" stroke="{}" stroke-width="3">{}</circle>"#,
+            x,
+            y,
+            CELL * 0.45,
+            FIRE,
+            anim
+        )
+    }
+
+    fn frame(&self, out: &mut String, i: usize) -> fmt::Result {
+        let still = &self.film.stills[i];
+        self.open(out)?;
+        for (id, trail) in self.trails.iter() {
+            let end = trail.partition_point(|(frame, _)| *frame <= i);
+            if end > 1 {
+                self.trail(out, *id, &trail[..end])?;
+            }
+        }
+        for shot in still.shots.iter() {
+            self.shot(out, *shot, "")?;
+        }
+        for mark in still.units.iter() {
+            let (x, y) = self.centre(mark.pos);
+            write!(out, r#"<g transform="translate({},{})">"#, x, y)?;
+            let fill = if mark.alive { self.colour(mark.id) } else { WRECK };
+            self.unit(out, mark.id, fill, "")?;
+            write!(out, "</g>")?;
+            if still.hit.contains(&mark.id) {
+                self.hit(out, mark.pos, "")?;
+            }
+        }
+        write!(
+            out,
+            r#"<text x="6" y="17" font-size="14">tick {}</text></svg>"#,
+            still.tick
+        )
+    }
+
+    fn animated(&self, out: &mut String, frame: Duration) -> fmt::Result {
+        let stills = &self.film.stills;
+        let frames = stills.len().max(1);
+        let dur = frame.as_secs_f32() * frames as f32;
+        let show = |from: usize, to: usize| {
+            let mut anim = String::new();
+            animate(
+                &mut anim,
+                r#"animate attributeName="opacity""#,
+                &shown(from, to, frames),
+                frames,
+                dur,
+            )
+            .map(|_| anim)
+        };
+        self.open(out)?;
+        for (id, trail) in self.trails.iter() {
+            for pair in trail.windows(2) {
+                let ((x1, y1), (x2, y2)) = (self.centre(pair[0].1), self.centre(pair[1].1));
+                write!(
+                    out,
+                    r#"<line x1="{}" y1="{}" x2="{}" y2="{}" stroke="{}" stroke-opacity="0.4" stroke-width="3">{}</line>"#,
+                    x1,
+                    y1,
+                    x2,
+                    y2,
+                    self.colour(*id),
+                    show(pair[1].0, frames)?
+                )?;
+            }
+        }
+        for (i, still) in stills.iter().enumerate() {
+            for shot in still.shots.iter() {
+                self.shot(out, *shot, &show(i, i + 1)?)?;
+            }
+            for id in still.hit.iter() {
+                if let Some(mark) = still.units.iter().find(|mark| mark.id == *id) {
+                    self.hit(out, mark.pos, &show(i, i + 1)?)?;
+                }
+            }
+        }
+        for (id, trail) in self.trails.iter() {
+            let first = trail[0].0;
+            let wrecked = stills
+                .iter()
+                .position(|still| still.units.iter().any(|mark| mark.id == *id && !mark.alive));
+            let mut fill = vec![(0, self.colour(*id).to_string())];
+            if let Some(wrecked) = wrecked {
+                fill.push((wrecked, WRECK.to_string()));
+            }
+            let mut fill_anim = String::new();
+            animate(&mut fill_anim, r#"animate attributeName="fill""#, &fill, frames, dur)?;
+            let places: Vec<(usize, String)> = trail
+                .iter()
+                .map(|(i, pos)| {
+                    let (x, y) = self.centre(*pos);
+                    (*i, format!("{},{}", x, y))
+                })
+                .collect();
+            write!(out, "<g>")?;
+            self.unit(out, *id, self.colour(*id), &fill_anim)?;
+            animate(
+                out,
+                r#"animateTransform attributeName="transform" type="translate""#,
+                &places,
+                frames,
+                dur,
+            )?;
+            if first > 0 {
+                write!(out, "{}", show(first, frames)?)?;
+            }
+            write!(out, "</g>")?;
+        }
+        for (i, still) in stills.iter().enumerate() {
+            write!(
+                out,
+                r#"<text x="6" y="17" font-size="14">tick {}{}</text>"#,
+                still.tick,
+                show(i, i + 1)?
+            )?;
+        }
+        write!(out, "</svg>")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        arsenal::{engine::EngineType, ModType, UnitActionContainer},
+        kikan::{Move, Unit},
+        map::Map,
+    };
+
+    #[test]
+    fn film() {
+        let mut kikan = Kikan::new(|| Position(0, 0));
+        let mut map = Map::new(5, 5);
+        map.add_wall(Position(2, 2)).set_terrain(Position(0, 1), Terrain::Water);
+        kikan.set_map(map);
+        let mut film = Film::new(&kikan);
+        let mut unit = Unit::builder();
+        unit.set_engine(EngineType::Light)
+            .set_team("red".to_string())
+            .add_mods("cannon".parse::<ModType>().unwrap().into_mod(), "gun".to_string());
+        let shooter = kikan.add_unit(Position(0, 0), unit).unwrap();
+        let mut unit = Unit::builder();
+        unit.set_engine(EngineType::STE).set_health(10);
+        let target = kikan.add_unit(Position(0, 3), unit).unwrap();
+
+        kikan.plan_unit_move(shooter, Move::N).unwrap();
+        kikan
+            .unit_mod_action(shooter, "gun".to_string(), UnitActionContainer::Pos(Position(0, 3)))
+            .unwrap();
+        for _ in 0..30 {
+            kikan.update().unwrap();
+            film.shoot(&kikan);
+        }
+        let stills = film.stills();
+        assert_eq!(stills.len(), 30);
+        assert_eq!(stills[0].shots, vec![(Position(0, 0), Position(0, 3))]);
+        assert!(stills.iter().any(|still| still.hit == vec![target]));
+        assert!(!stills[29].units[1].alive);
+        assert_eq!(stills[29].units[0].pos, Position(1, 0));
+
+        let frames = film.frames();
+        assert_eq!(frames.len(), 30);
+        assert!(frames[0].starts_with("<svg") && frames[0].ends_with("</svg>"));
+        assert!(!frames[0].contains("<polyline"));
+        assert!(frames[29].contains("<polyline"));
+        assert!(frames[29].contains(WRECK));
+        assert_eq!(frames[29].matches("<rect").count(), 1 + 25);
+
+        let animated = film.animated(Duration::from_millis(100));
+        assert!(animated.contains(r#"dur="3.000s""#));
+        assert_eq!(animated.matches("animateTransform").count(), 2);
+        assert_eq!(animated.matches(">tick ").count(), 30);
+    }
+}
@@ -0,0 +1,453 @@
+use crate::{
+    arsenal::{engine::Route, ModType, UnitActionContainer},
+    error::{KResult, KikanError},
+    event::Event,
+    kikan::{Kikan, Move, Position, Rules, UnitId, UnitOrigin},
+    map::{Map, Terrain},
+    topology::Topology,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fmt,
+    fs::File,
+    io::{BufRead, BufReader, Write},
+    path::Path,
+    slice,
+};
+
+/// Bumped whenever old replays can not be played back anymore.
+pub const REPLAY_VERSION: u32 = 1;
+
+/// The world before the first update, without units.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct World {
+    pub size: Option<(u32, u32)>,
+    pub walls: Vec<Position>,
+    pub terrain: Vec<(Position, Terrain)>,
+    pub topology: Topology,
+    pub rules: Rules,
+}
+
+impl World {
+    pub fn of(kikan: &Kikan) -> Self {
+        let map = kikan.map();
+        Self {
+            size: map.size(),
+            walls: map.walls().copied().collect(),
+            terrain: map.terrain_cells().map(|(pos, terrain)| (*pos, *terrain)).collect(),
+            topology: kikan.topology(),
+            rules: kikan.rules(),
+        }
+    }
+
+    pub fn build(&self) -> Kikan {
+        let mut map = match self.size {
+            Some((height, width)) => Map::new(height, width),
+            None => Map::open(),
+        };
+        for pos in self.walls.iter() {
+            map.add_wall(*pos);
+        }
+        for (pos, terrain) in self.terrain.iter() {
+            map.set_terrain(*pos, *terrain);
+        }
+        // every unit is placed by an order
+        let mut kikan = Kikan::new(|| Position(0, 0));
+        kikan.set_map(map);
+        kikan.set_topology(self.topology);
+        kikan.set_rules(self.rules);
+        kikan
+    }
+}
+
+/// What a unit was built from, mods by id.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UnitSpec {
+    pub engine: Option<String>,
+    pub mods: BTreeMap<String, String>,
+    pub health: u32,
+    pub armor: u32,
+    pub max_energy: u32,
+    pub energy_regen: u32,
+    pub name: Option<String>,
+    pub team: Option<String>,
+}
+
+impl UnitSpec {
+    pub fn of(origin: &UnitOrigin) -> Self {
+        Self {
+            engine: origin.engine.map(|engine| engine.as_str().to_string()),
+            mods: origin
+                .mods
+                .iter()
+                .map(|(id, umod)| (id.clone(), umod.name().to_string()))
+                .collect(),
+            health: origin.health,
+            armor: origin.armor,
+            max_energy: origin.max_energy,
+            energy_regen: origin.energy_regen,
+            name: origin.name.clone(),
+            team: origin.team.clone(),
+        }
+    }
+
+    pub fn origin(&self) -> KResult<UnitOrigin> {
+        let mut origin = UnitOrigin::new();
+        if let Some(engine) = &self.engine {
+            origin.set_engine(engine.parse()?);
+        }
+        for (id, umod) in self.mods.iter() {
+            origin.add_mods(umod.parse::<ModType>()?.into_mod(), id.clone());
+        }
+        origin
+            .set_health(self.health)
+            .set_armor(self.armor)
+            .set_energy(self.max_energy, self.energy_regen);
+        if let Some(name) = &self.name {
+            origin.set_name(name.clone());
+        }
+        if let Some(team) = &self.team {
+            origin.set_team(team.clone());
+        }
+        Ok(origin)
+    }
+}
+
+/// A call from outside which changed the world.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "order", rename_all = "snake_case")]
+pub enum Order {
+    AddUnit {
+        id: UnitId,
+        pos: Position,
+        unit: UnitSpec,
+    },
+    Move {
+        unit: UnitId,
+        direction: Move,
+    },
+    /// routes to a cell are stored as the moves found for them
+    Path {
+        unit: UnitId,
+        moves: Vec<Move>,
+    },
+    ModAction {
+        unit: UnitId,
+        mod_id: String,
+        target: Position,
+    },
+    Disqualify {
+        unit: UnitId,
+    },
+}
+
+impl Order {
+    fn apply(&self, kikan: &mut Kikan) -> KResult<()> {
+        match self {
+            Self::AddUnit { id, pos, unit } => {
+                let origin = unit.origin()?;
+                if kikan.add_unit(*pos, origin)? != *id {
+                    return Err(KikanError::GhostUnit);
+                }
+                Ok(())
+            }
+            Self::Move { unit, direction } => kikan.plan_unit_move(*unit, *direction),
+            Self::Path { unit, moves } => kikan.plan_unit_path(*unit, Route::Moves(moves.clone())),
+            Self::ModAction { unit, mod_id, target } => {
+                kikan.unit_mod_action(*unit, mod_id.clone(), UnitActionContainer::Pos(*target))
+            }
+            Self::Disqualify { unit } => kikan.disqualify_unit(*unit),
+        }
+    }
+}
+
+impl fmt::Display for Order {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::AddUnit { id, pos, unit } => write!(
+                f,
+                "#{} {} placed at ({}, {})",
+                id,
+                unit.name.as_deref().unwrap_or("?"),
+                pos.0,
+                pos.1
+            ),
+            Self::Move { unit, direction } => write!(f, "#{} moves {}", unit, direction.as_str()),
+            Self::Path { unit, moves } => {
+                let moves: Vec<&str> = moves.iter().map(|m| m.as_str()).collect();
+                write!(f, "#{} follows {}", unit, moves.join(" "))
+            }
+            Self::ModAction { unit, mod_id, target } => {
+                write!(f, "#{} aims {} at ({}, {})", unit, mod_id, target.0, target.1)
+            }
+            Self::Disqualify { unit } => write!(f, "#{} disqualified", unit),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Header {
+    version: u32,
+    world: World,
+}
+
+/// One line of a replay: the orders given since the last update, then what the update did.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReplayTick {
+    pub tick: u64,
+    pub orders: Vec<Order>,
+    pub events: Vec<Event>,
+}
+
+/// Writes a replay as JSON lines, a header and then one line per update.
+pub(crate) struct Recorder {
+    out: Box<dyn Write + Send>,
+    orders: Vec<Order>,
+}
+
+impl Recorder {
+    pub(crate) fn new(mut out: Box<dyn Write + Send>, world: World) -> KResult<Self> {
+        let header = Header {
+            version: REPLAY_VERSION,
+            world,
+        };
+        write_line(&mut out, &header)?;
+        Ok(Self {
+            out,
+            orders: Vec::new(),
+        })
+    }
+
+    pub(crate) fn order(&mut self, order: Order) {
+        self.orders.push(order);
+    }
+
+    pub(crate) fn tick(&mut self, tick: u64, events: &[Event]) -> KResult<()> {
+        let line = ReplayTick {
+            tick,
+            orders: std::mem::take(&mut self.orders),
+            events: events.to_vec(),
+        };
+        write_line(&mut self.out, &line)?;
+        // a replay is most wanted after a crash
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+fn write_line<T: Serialize>(out: &mut dyn Write, value: &T) -> KResult<()> {
+    let line = serde_json::to_string(value).map_err(|e| KikanError::ReplaySyntax(e.to_string()))?;
+    writeln!(out, "{}", line)?;
+    Ok(())
+}
+
+/// A recorded match.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Replay {
+    pub world: World,
+    pub ticks: Vec<ReplayTick>,
+}
+
+impl Replay {
+    pub fn load<P: AsRef<Path>>(path: P) -> KResult<Self> {
+        Self::read(BufReader::new(File::open(path)?))
+    }
+
+    pub fn read<R: BufRead>(reader: R) -> KResult<Self> {
+        let syntax = |e: serde_json::Error| KikanError::ReplaySyntax(e.to_string());
+        let mut lines = reader.lines();
+        let first = lines
+            .next()
+            .ok_or_else(|| KikanError::ReplaySyntax("empty file".to_string()))??;
+        // the version is checked before anything else can fail to parse
+        let version: serde_json::Value = serde_json::from_str(&first).map_err(syntax)?;
+        match version.get("version").and_then(|version| version.as_u64()) {
+            Some(version) if version == REPLAY_VERSION as u64 => {}
+            Some(version) => return Err(KikanError::ReplayVersion(version)),
+            None => return Err(KikanError::ReplaySyntax("no version".to_string())),
+        }
+        let header: Header = serde_json::from_value(version).map_err(syntax)?;
+        let mut ticks = Vec::new();
+        for line in lines {
+            let line = line?;
+            if !line.trim().is_empty() {
+                ticks.push(serde_json::from_str(&line).map_err(syntax)?);
+            }
+        }
+        Ok(Self {
+            world: header.world,
+            ticks,
+        })
+    }
+
+    /// Plays the match again from the start.
+    pub fn player(&self) -> Player<'_> {
+        Player {
+            kikan: self.world.build(),
+            ticks: self.ticks.iter(),
+        }
+    }
+}
+
+/// Re-simulates a replay one update at a time, checking it goes the same way.
+pub struct Player<'a> {
+    kikan: Kikan,
+    ticks: slice::Iter<'a, ReplayTick>,
+}
+
+impl<'a> Player<'a> {
+    pub fn kikan(&self) -> &Kikan {
+        &self.kikan
+    }
+
+    /// Gives the orders of the next tick and updates, `None` at the end of the replay.
+    pub fn step(&mut self) -> KResult<Option<&'a ReplayTick>> {
+        let tick = match self.ticks.next() {
+            Some(tick) => tick,
+            None => return Ok(None),
+        };
+        for order in tick.orders.iter() {
+            order
+                .apply(&mut self.kikan)
+                .map_err(|e| KikanError::ReplayDiverged(tick.tick, format!("{:?} failed: {}", order, e)))?;
+        }
+        // a failed commit does not end the match, the events still have to agree
+        self.kikan.update().ok();
+        if self.kikan.tick() != tick.tick {
+            return Err(KikanError::ReplayDiverged(
+                tick.tick,
+                format!("the world is at tick {}", self.kikan.tick()),
+            ));
+        }
+        if self.kikan.last_events() != tick.events.as_slice() {
+            return Err(KikanError::ReplayDiverged(tick.tick, "other events".to_string()));
+        }
+        Ok(Some(tick))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{arsenal::engine::EngineType, kikan::Unit};
+    use std::{
+        io::Cursor,
+        sync::{Arc, Mutex},
+    };
+
+    #[derive(Clone, Default)]
+    struct Tape(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Tape {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn record() -> (Kikan, Vec<u8>) {
+        let tape = Tape::default();
+        let mut kikan = Kikan::new(|| Position(0, 0));
+        let mut map = Map::new(6, 6);
+        map.add_wall(Position(3, 0)).set_terrain(Position(1, 1), Terrain::Mud);
+        kikan.set_map(map);
+        kikan.record_to(Box::new(tape.clone())).unwrap();
+
+        let mut unit0 = Unit::builder();
+        unit0.set_engine(EngineType::Light).set_name("a".to_string());
+        unit0.add_mods("cannon".parse::<ModType>().unwrap().into_mod(), "gun".to_string());
+        let u0 = kikan.add_unit(Position(0, 0), unit0).unwrap();
+        let mut unit1 = Unit::builder();
+        unit1.set_engine(EngineType::STE).set_health(50);
+        let u1 = kikan.add_unit(Position(0, 4), unit1).unwrap();
+
+        kikan.plan_unit_path(u0, Route::To(Position(5, 0))).unwrap();
+        kikan
+            .unit_mod_action(u0, "gun".to_string(), UnitActionContainer::Pos(Position(0, 4)))
+            .unwrap();
+        for tick in 0..80 {
+            if tick == 10 {
+                kikan.plan_unit_move(u1, Move::N).unwrap();
+            }
+            kikan.update().unwrap();
+        }
+        let bytes = tape.0.lock().unwrap().clone();
+        (kikan, bytes)
+    }
+
+    #[test]
+    fn play_back() {
+        let (mut kikan, bytes) = record();
+        assert!(kikan.record_to(Box::new(Tape::default())).is_err());
+        let replay = Replay::read(Cursor::new(bytes)).unwrap();
+        assert_eq!(replay.world, World::of(&kikan));
+        assert_eq!(replay.ticks.len(), 80);
+        assert_eq!(replay.ticks[0].orders.len(), 4);
+
+        let mut player = replay.player();
+        while player.step().unwrap().is_some() {}
+        for id in kikan.unit_ids() {
+            assert_eq!(player.kikan().get_unit_position(id), kikan.get_unit_position(id));
+            assert_eq!(player.kikan().get_unit_health(id), kikan.get_unit_health(id));
+        }
+        assert_eq!(kikan.get_unit_health(1), Some(10));
+    }
+
+    struct FullDisk;
+
+    impl Write for FullDisk {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Err(std::io::Error::other("disk full"))
+        }
+    }
+
+    #[test]
+    fn recording_fails() {
+        let mut kikan = Kikan::new(|| Position(0, 0));
+        kikan.record_to(Box::new(FullDisk)).unwrap();
+        let mut unit = Unit::builder();
+        unit.set_engine(EngineType::STE);
+        let id = kikan.add_unit(Position(0, 0), unit).unwrap();
+        kikan.plan_unit_move(id, Move::N).unwrap();
+        for _ in 0..20 {
+            kikan.update().unwrap();
+        }
+        assert!(kikan.recording_failed().is_some_and(|e| e.contains("disk full")));
+        assert_eq!(kikan.get_unit_position(id), Some(Position(1, 0)));
+    }
+
+    #[test]
+    fn bad_replays() {
+        let (_, bytes) = record();
+        let text = String::from_utf8(bytes).unwrap();
+        let newer = text.replacen("\"version\":1", "\"version\":99", 1);
+        assert!(matches!(
+            Replay::read(Cursor::new(newer)),
+            Err(KikanError::ReplayVersion(99))
+        ));
+        assert!(matches!(
+            Replay::read(Cursor::new("")),
+            Err(KikanError::ReplaySyntax(_))
+        ));
+
+        // another world, the same orders
+        let mut replay = Replay::read(Cursor::new(text)).unwrap();
+        replay.world.walls.push(Position(1, 4));
+        let mut player = replay.player();
+        let res = loop {
+            match player.step() {
+                Ok(Some(_)) => continue,
+                res => break res,
+            }
+        };
+        assert!(matches!(res, Err(KikanError::ReplayDiverged(_, cause)) if cause == "other events"));
+    }
+}
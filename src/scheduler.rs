@@ -0,0 +1,121 @@
+use crate::error::{KResult, KikanError};
+use std::{
+    cell::Cell,
+    sync::mpsc::{channel, Receiver, Sender},
+};
+
+/// Script side of a seat, passed to the handler of a script.
+pub struct Turn {
+    go: Receiver<()>,
+    done: Sender<()>,
+    /// lua instructions one turn may take
+    budget: Option<u32>,
+    used: Cell<u32>,
+}
+
+impl Turn {
+    /// Block until the scheduler hands the turn to this script.
+    pub fn wait(&self) -> KResult<()> {
+        self.go.recv().map_err(|_| KikanError::MatchOver)
+    }
+
+    /// Give the turn back and wait for the next one.
+    pub fn end(&self) -> KResult<()> {
+        self.used.set(0);
+        self.done.send(()).map_err(|_| KikanError::MatchOver)?;
+        self.wait()
+    }
+
+    /// Count instructions run in this turn, the turn ends once the budget is spent.
+    pub fn spend(&self, instructions: u32) -> KResult<()> {
+        let used = self.used.get().saturating_add(instructions);
+        self.used.set(used);
+        match self.budget {
+            Some(budget) if used >= budget => self.end(),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Scheduler side of a seat.
+/// A script leaves its seat by dropping the [`Turn`].
+pub struct Seat {
+    go: Sender<()>,
+    done: Receiver<()>,
+    left: bool,
+}
+
+impl Seat {
+    pub fn new(budget: Option<u32>) -> (Self, Turn) {
+        let (go_tx, go_rx) = channel();
+        let (done_tx, done_rx) = channel();
+        let seat = Self {
+            go: go_tx,
+            done: done_rx,
+            left: false,
+        };
+        let turn = Turn {
+            go: go_rx,
+            done: done_tx,
+            budget,
+            used: Cell::new(0),
+        };
+        (seat, turn)
+    }
+
+    pub fn has_left(&self) -> bool {
+        self.left
+    }
+
+    /// Run the script until it gives the turn back.
+    pub fn play(&mut self) {
+        if self.left {
+            return;
+        }
+        if self.go.send(()).is_err() || self.done.recv().is_err() {
+            self.left = true;
+        }
+    }
+}
+
+/// Gives every seat exactly one turn per round, always in the same order.
+/// A turn lasts until the script waits for an update or spends its instruction budget.
+#[derive(Default)]
+pub struct Scheduler {
+    seats: Vec<Seat>,
+    turn_budget: Option<u32>,
+}
+
+impl Scheduler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_turn_budget(turn_budget: Option<u32>) -> Self {
+        Self {
+            seats: Vec::new(),
+            turn_budget,
+        }
+    }
+
+    pub fn take_seat(&mut self) -> Turn {
+        let (seat, turn) = Seat::new(self.turn_budget);
+        self.seats.push(seat);
+        turn
+    }
+
+    pub fn play_round(&mut self) {
+        for seat in self.seats.iter_mut() {
+            seat.play();
+        }
+    }
+
+    /// Which seats are empty, in seat order.
+    pub fn left(&self) -> Vec<bool> {
+        self.seats.iter().map(|seat| seat.has_left()).collect()
+    }
+
+    pub fn all_left(&self) -> bool {
+        self.seats.iter().all(|seat| seat.has_left())
+    }
+}
@@ -1,8 +1,26 @@
-use crate::{error::KikanError, handler::UnitHandler};
-use mlua::Lua;
+use crate::{
+    error::{KikanError, ScriptLimit},
+    handler::{Handler, UnitHandler},
+};
+use mlua::{AnyUserData, Error as LuaError, HookTriggers, Lua, LuaOptions, MultiValue, StdLib, Table, Value};
+use std::{cell::Cell, rc::Rc};
+
+/// registry key of the handler, scripts may overwrite the global
+const API_KEY: &str = "kikan.api";
+/// lua instructions between two hook calls
+const HOOK_STEP: u32 = 1000;
+/// `pcall` and `xpcall` hand what they caught to `check` first
+const CATCHERS: &str = r#"
+    local pcall, xpcall, check = pcall, xpcall, ...
+    _G.pcall = function(...) return check(pcall(...)) end
+    _G.xpcall = function(...) return check(xpcall(...)) end
+"#;
 
 mod utils {
-    use crate::{arsenal::engine::EngineType, kikan::Position};
+    use crate::{
+        arsenal::{engine::EngineType, ModType},
+        kikan::Position,
+    };
     use mlua::UserData;
 
     pub struct Utils {}
@@ -12,37 +30,191 @@ mod utils {
         fn add_methods<'lua, M: mlua::UserDataMethods<'lua, Self>>(methods: &mut M) {
             methods.add_method("new_position", |_, _, (x, y): (i32, i32)| Ok(Position(x, y)));
             methods.add_method("new_engine", |_, _, engine: String| Ok(engine.parse::<EngineType>()?));
+            methods.add_method("new_mod", |_, _, umod: String| Ok(umod.parse::<ModType>()?));
+        }
+    }
+}
+
+/// Sandbox of one script, limits are `None` for no limit.
+#[derive(Debug, Clone)]
+pub struct ScriptConfig {
+    /// lua instructions over the whole life of the script
+    pub max_instructions: Option<u64>,
+    /// bytes
+    pub max_memory: Option<usize>,
+    /// libraries on top of [`sandbox_libs`]
+    pub extra_libs: StdLib,
+    /// seed of `math.random`
+    pub seed: u64,
+}
+
+impl Default for ScriptConfig {
+    fn default() -> Self {
+        Self {
+            max_instructions: None,
+            max_memory: None,
+            extra_libs: StdLib::NONE,
+            seed: 0,
         }
     }
 }
 
+/// Libraries every script gets.
+pub fn sandbox_libs() -> StdLib {
+    StdLib::STRING | StdLib::TABLE | StdLib::MATH
+}
+
+/// Library names as seen from lua.
+pub fn parse_lib(name: &str) -> Result<StdLib, KikanError> {
+    Ok(match name {
+        "coroutine" => StdLib::COROUTINE,
+        "table" => StdLib::TABLE,
+        "io" => StdLib::IO,
+        "os" => StdLib::OS,
+        "string" => StdLib::STRING,
+        "utf8" => StdLib::UTF8,
+        "math" => StdLib::MATH,
+        "package" => StdLib::PACKAGE,
+        _ => return Err(KikanError::NoSuchLibrary(name.to_string())),
+    })
+}
+
+/// Drop base functions reaching the file system and make `math.random` reproducible.
+fn sandbox(lua: &Lua, seed: u64) -> Result<(), KikanError> {
+    let globals = lua.globals();
+    for name in ["dofile", "loadfile", "load"] {
+        globals.set(name, Value::Nil)?;
+    }
+    let math: Table = globals.get("math")?;
+    math.get::<_, mlua::Function>("randomseed")?
+        .call::<_, ()>(seed as i64)?;
+    // without arguments it would seed from the clock
+    math.set("randomseed", Value::Nil)?;
+    Ok(())
+}
+
 pub fn load_lua_script<T, H>(handler: H, lua_script: T) -> Result<(), KikanError>
 where
     T: AsRef<str>,
     H: 'static + UnitHandler,
 {
-    let lua = Lua::new();
+    load_lua_script_with(handler, lua_script, &ScriptConfig::default())
+}
+
+/// A script over its limits gets its unit disqualified.
+pub fn load_lua_script_with<T, H>(handler: H, lua_script: T, config: &ScriptConfig) -> Result<(), KikanError>
+where
+    T: AsRef<str>,
+    H: 'static + UnitHandler,
+{
+    let lua = Lua::new_with(sandbox_libs() | config.extra_libs, LuaOptions::default())?;
     // library
+    sandbox(&lua, config.seed)?;
     // init unit
-    let handler = handler.package();
+    let handler = lua.create_userdata(handler.package())?;
+    lua.set_named_registry_value(API_KEY, handler.clone())?;
     lua.globals().set("api", handler)?;
 
+    let exceeded: Rc<Cell<Option<ScriptLimit>>> = Rc::default();
+    let hook_exceeded = Rc::clone(&exceeded);
+    let max_instructions = config.max_instructions;
+    let mut instructions: u64 = 0;
+    lua.set_hook(
+        HookTriggers {
+            every_nth_instruction: Some(HOOK_STEP),
+            ..HookTriggers::default()
+        },
+        move |lua, _| {
+            let handler: AnyUserData = lua.named_registry_value(API_KEY)?;
+            let handler = handler.borrow::<Handler<H>>()?;
+            instructions += HOOK_STEP as u64;
+            if max_instructions.is_some_and(|max| instructions > max) {
+                drop(handler);
+                return exceed::<H>(lua, &hook_exceeded, ScriptLimit::Instructions);
+            }
+            Ok(handler.0.spend_instructions(HOOK_STEP)?)
+        },
+    )?;
+    if config.max_memory.is_some() {
+        // a memory error caught by the script ends it all the same
+        let catch_exceeded = Rc::clone(&exceeded);
+        let check = lua.create_function(move |lua, caught: MultiValue| {
+            let out_of_memory = match caught.iter().take(2).collect::<Vec<_>>()[..] {
+                [Value::Boolean(false), Value::String(e)] => e.as_bytes() == b"not enough memory",
+                [Value::Boolean(false), Value::Error(e)] => is_memory_error(e),
+                _ => false,
+            };
+            if out_of_memory {
+                exceed::<H>(lua, &catch_exceeded, ScriptLimit::Memory)?;
+            }
+            Ok(caught)
+        })?;
+        lua.load(CATCHERS).call::<_, ()>(check)?;
+    }
+
     // insert help functions
     let utils = utils::Utils {};
     lua.globals().set("utils", utils)?;
 
+    if let Some(max_memory) = config.max_memory {
+        // counted from here, the sandbox itself is free
+        lua.set_memory_limit(lua.used_memory() + max_memory)?;
+    }
+
     // run script
     let script = lua.load(lua_script.as_ref());
-    script.exec()?;
-    Ok(())
+    match script.exec() {
+        Ok(()) => Ok(()),
+        Err(e) => {
+            if exceeded.get().is_none() && is_memory_error(&e) {
+                exceed::<H>(&lua, &exceeded, ScriptLimit::Memory).ok();
+            }
+            match exceeded.get() {
+                Some(limit) => Err(KikanError::LimitExceeded(limit)),
+                None => Err(e.into()),
+            }
+        }
+    }
+}
+
+/// Disqualify the unit and fail every instruction from now on, `pcall` can not keep the script alive.
+fn exceed<H: 'static + UnitHandler>(
+    lua: &Lua,
+    exceeded: &Cell<Option<ScriptLimit>>,
+    limit: ScriptLimit,
+) -> mlua::Result<()> {
+    exceeded.set(Some(limit));
+    // lift the memory limit, looking the handler up allocates
+    lua.set_memory_limit(0)?;
+    let handler: AnyUserData = lua.named_registry_value(API_KEY)?;
+    handler.borrow::<Handler<H>>()?.0.disqualify()?;
+    lua.set_hook(
+        HookTriggers {
+            every_nth_instruction: Some(1),
+            ..HookTriggers::default()
+        },
+        move |_, _| Err(KikanError::LimitExceeded(limit).into()),
+    )?;
+    Err(KikanError::LimitExceeded(limit).into())
+}
+
+fn is_memory_error(e: &LuaError) -> bool {
+    match e {
+        LuaError::MemoryError(_) => true,
+        LuaError::CallbackError { cause, .. } => is_memory_error(cause),
+        _ => false,
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::{
+        arsenal::engine::EngineType,
         handler::LocalHandle,
-        kikan::{Kikan, Position},
+        kikan::{Kikan, Move, Position, Rules, Unit, DEFAULT_HEALTH},
+        map::{Map, Terrain},
+        topology::Topology,
     };
     use std::sync::{
         atomic::{AtomicI32, Ordering},
@@ -112,6 +284,299 @@ mod tests {
         assert_eq!(kikan.lock().unwrap().get_unit_position(0), Some(Position(2, 2)));
     }
 
+    #[test]
+    fn plan_path() {
+        let script = r#"
+            api:set_engine(utils:new_engine("ste"))
+            api:init()
+            api:plan_path({'N', 'NE'})
+            while api:is_moving() do
+                api:wait_for_update()
+            end
+            local pos = api:get_position()
+            assert(pos.x == 2 and pos.y == 1)
+            api:plan_path(utils:new_position(0, 3))
+            while api:is_moving() do
+                api:wait_for_update()
+            end
+            assert(not api:path_interrupted())
+            api:plan_move('se')
+        "#;
+        let kikan = Kikan::kikan_in_a_shell(|| Position(0, 0));
+        let handler = LocalHandle::new(Arc::clone(&kikan));
+        let _world = {
+            let kikan = Arc::clone(&kikan);
+            std::thread::spawn(move || loop {
+                kikan.lock().unwrap().update().unwrap();
+            })
+        };
+        load_lua_script(handler, script).unwrap();
+        assert_eq!(kikan.lock().unwrap().get_unit_position(0), Some(Position(0, 3)));
+    }
+
+    #[test]
+    fn last_events() {
+        let script = r#"
+            local events = api:last_events()
+            assert(#events == 1)
+            assert(events[1].kind == "moved" and events[1].unit == 0)
+            assert(events[1].from.x == 0 and events[1].to.x == 1 and events[1].to.y == 0)
+        "#;
+        let kikan = Kikan::kikan_in_a_shell(|| Position(0, 0));
+        {
+            let mut kikan = kikan.lock().unwrap();
+            let mut unit = Unit::builder();
+            unit.set_engine(EngineType::STE);
+            let id = kikan.add_unit(Position(0, 0), unit).unwrap();
+            kikan.plan_unit_move(id, Move::N).unwrap();
+            while kikan.is_unit_moving(id).unwrap() {
+                kikan.update().unwrap();
+            }
+        }
+        let handler = LocalHandle::new(kikan);
+        load_lua_script(handler, script).unwrap();
+    }
+
+    #[test]
+    fn topology() {
+        let script = r#"
+            api:set_engine(utils:new_engine("ste"))
+            api:init()
+            assert(api:topology() == "hex")
+            assert(#api:moves() == 6)
+            assert(api:distance(utils:new_position(0, 0), utils:new_position(3, -3)) == 3)
+            assert(not pcall(function() api:plan_move('NE') end))
+            api:plan_move('SE')
+        "#;
+        let kikan = Kikan::kikan_in_a_shell(|| Position(0, 0));
+        kikan.lock().unwrap().set_topology(Topology::Hex);
+        let handler = LocalHandle::new(Arc::clone(&kikan));
+        load_lua_script(handler, script).unwrap();
+    }
+
+    #[test]
+    fn add_mod() {
+        let script = r#"
+            api:set_engine(utils:new_engine("ste"))
+            api:add_mod(utils:new_mod("kinetic"), "gun")
+            api:init()
+            api:mod_on("gun", utils:new_position(0, 3))
+            assert(api:energy() == 90)
+        "#;
+        let kikan = Kikan::kikan_in_a_shell(|| Position(0, 0));
+        let target = {
+            let mut target = Unit::builder();
+            target.set_engine(EngineType::STE);
+            kikan.lock().unwrap().add_unit(Position(0, 3), target).unwrap()
+        };
+        let handler = LocalHandle::new(Arc::clone(&kikan));
+        load_lua_script(handler, script).unwrap();
+        let mut kikan = kikan.lock().unwrap();
+        for _ in 0..5 {
+            kikan.update().unwrap();
+        }
+        assert!(kikan.get_unit_health(target).unwrap() < DEFAULT_HEALTH);
+    }
+
+    #[test]
+    fn terrain() {
+        let script = r#"
+            api:set_engine(utils:new_engine("ste"))
+            api:init()
+            assert(api:terrain(utils:new_position(0, 1)) == "water")
+            assert(api:terrain(utils:new_position(1, 0)) == "open")
+            assert(not pcall(function() api:plan_move('E') end))
+        "#;
+        let kikan = Kikan::kikan_in_a_shell(|| Position(0, 0));
+        let mut map = Map::open();
+        map.set_terrain(Position(0, 1), Terrain::Water);
+        kikan.lock().unwrap().set_map(map);
+        let handler = LocalHandle::new(Arc::clone(&kikan));
+        load_lua_script(handler, script).unwrap();
+    }
+
+    #[test]
+    fn budget() {
+        let script = r#"
+            assert(api:budget_remaining() == 60)
+            api:set_engine(utils:new_engine("ste"))
+            api:add_mod(utils:new_mod("railgun"), "gun")
+            assert(api:budget_remaining() == -5)
+            assert(not pcall(function() api:init() end))
+            api:set_engine(utils:new_engine("light"))
+            api:init()
+            assert(api:budget_remaining() == 0)
+        "#;
+        let kikan = Kikan::kikan_in_a_shell(|| Position(0, 0));
+        kikan.lock().unwrap().set_rules(Rules {
+            budget: Some(60),
+            ..Rules::default()
+        });
+        let handler = LocalHandle::new(Arc::clone(&kikan));
+        load_lua_script(handler, script).unwrap();
+        assert!(kikan.lock().unwrap().is_unit_alive(0));
+
+        let kikan = Kikan::kikan_in_a_shell(|| Position(0, 0));
+        let handler = LocalHandle::new(kikan);
+        load_lua_script(handler, "assert(api:budget_remaining() == nil)").unwrap();
+    }
+
+    #[test]
+    fn add_mod_after_init() {
+        let script = r#"
+            api:set_engine(utils:new_engine("ste"))
+            api:init()
+            api:add_mod(utils:new_mod("railgun"), "gun")
+        "#;
+        let kikan = Kikan::kikan_in_a_shell(|| Position(0, 0));
+        let handler = LocalHandle::new(Arc::clone(&kikan));
+        assert!(load_lua_script(handler, script).is_err());
+    }
+
+    #[test]
+    fn instruction_limit() {
+        let script = r#"
+            api:set_engine(utils:new_engine("ste"))
+            api:init()
+            while true do
+                pcall(function() while true do end end)
+            end
+        "#;
+        let kikan = Kikan::kikan_in_a_shell(|| Position(0, 0));
+        let handler = LocalHandle::new(Arc::clone(&kikan));
+        let config = ScriptConfig {
+            max_instructions: Some(100_000),
+            ..ScriptConfig::default()
+        };
+        assert!(matches!(
+            load_lua_script_with(handler, script, &config),
+            Err(KikanError::LimitExceeded(ScriptLimit::Instructions))
+        ));
+        assert!(matches!(
+            kikan.lock().unwrap().plan_unit_move(0, Move::N),
+            Err(KikanError::ModOffline)
+        ));
+    }
+
+    #[test]
+    fn memory_limit() {
+        let script = r#"
+            api:set_engine(utils:new_engine("ste"))
+            api:init()
+            local t = {}
+            for i = 1, 1000000 do
+                t[i] = string.rep("x", 64) .. i
+            end
+        "#;
+        let kikan = Kikan::kikan_in_a_shell(|| Position(0, 0));
+        let handler = LocalHandle::new(Arc::clone(&kikan));
+        let config = ScriptConfig {
+            max_memory: Some(1 << 20),
+            ..ScriptConfig::default()
+        };
+        assert!(matches!(
+            load_lua_script_with(handler, script, &config),
+            Err(KikanError::LimitExceeded(ScriptLimit::Memory))
+        ));
+        assert!(matches!(
+            kikan.lock().unwrap().plan_unit_move(0, Move::N),
+            Err(KikanError::ModOffline)
+        ));
+    }
+
+    #[test]
+    fn memory_limit_caught() {
+        let script = r#"
+            api:set_engine(utils:new_engine("ste"))
+            api:init()
+            local t = {}
+            local ok = pcall(function()
+                for i = 1, 1000000 do
+                    t[i] = string.rep("x", 64) .. i
+                end
+            end)
+            t = nil
+            -- keeps playing as if nothing happened
+            while true do
+                pcall(api.plan_move, api, "N")
+            end
+        "#;
+        let kikan = Kikan::kikan_in_a_shell(|| Position(0, 0));
+        let handler = LocalHandle::new(Arc::clone(&kikan));
+        let config = ScriptConfig {
+            max_memory: Some(1 << 20),
+            ..ScriptConfig::default()
+        };
+        assert!(matches!(
+            load_lua_script_with(handler, script, &config),
+            Err(KikanError::LimitExceeded(ScriptLimit::Memory))
+        ));
+        assert!(matches!(
+            kikan.lock().unwrap().plan_unit_move(0, Move::N),
+            Err(KikanError::ModOffline)
+        ));
+    }
+
+    #[test]
+    fn sandboxed() {
+        let script = r#"
+            assert(io == nil and os == nil and package == nil and require == nil)
+            assert(dofile == nil and loadfile == nil and load == nil)
+            assert(coroutine == nil and math.randomseed == nil)
+            assert(string.rep("a", 2) == "aa" and #table.pack(1, 2) == 2 and math.max(1, 2) == 2)
+        "#;
+        let kikan = Kikan::kikan_in_a_shell(|| Position(0, 0));
+        load_lua_script(LocalHandle::new(kikan), script).unwrap();
+    }
+
+    #[test]
+    fn extra_libs() {
+        let script = r#"
+            assert(coroutine.running ~= nil and os.time ~= nil)
+            assert(io == nil)
+        "#;
+        let kikan = Kikan::kikan_in_a_shell(|| Position(0, 0));
+        let config = ScriptConfig {
+            extra_libs: parse_lib("coroutine").unwrap() | parse_lib("os").unwrap(),
+            ..ScriptConfig::default()
+        };
+        load_lua_script_with(LocalHandle::new(kikan), script, &config).unwrap();
+        assert!(matches!(parse_lib("debug"), Err(KikanError::NoSuchLibrary(_))));
+    }
+
+    #[test]
+    fn seeded_random() {
+        // the draws come back in the error, so whole sequences are compared
+        let script = r#"
+            local draws = {}
+            for i = 1, 8 do
+                draws[i] = math.random(1000000)
+            end
+            error("draws " .. table.concat(draws, ","))
+        "#;
+        let draws = |seed| {
+            let kikan = Kikan::kikan_in_a_shell(|| Position(0, 0));
+            let config = ScriptConfig {
+                seed,
+                ..ScriptConfig::default()
+            };
+            let e = load_lua_script_with(LocalHandle::new(kikan), script, &config)
+                .unwrap_err()
+                .to_string();
+            let draws: String = e
+                .split("draws ")
+                .nth(1)
+                .unwrap()
+                .chars()
+                .take_while(|c| c.is_ascii_digit() || *c == ',')
+                .collect();
+            assert_eq!(draws.split(',').count(), 8);
+            draws
+        };
+        assert_eq!(draws(42), draws(42));
+        assert_ne!(draws(42), draws(43));
+    }
+
     #[test]
     fn double_init() {
         let script = r#"
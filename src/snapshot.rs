@@ -0,0 +1,177 @@
+use crate::{
+    arsenal::{CommitState, Energy, PartState},
+    error::{KResult, KikanError},
+    event::Event,
+    kikan::{Move, Position, UnitId},
+    replay::World,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+/// Bumped whenever old snapshots can not be restored anymore.
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+/// A unit with the state of every part.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UnitState {
+    pub id: UnitId,
+    pub pos: Position,
+    pub engine: PartState,
+    pub mods: BTreeMap<String, PartState>,
+    pub health: u32,
+    pub armor: u32,
+    pub energy: Energy,
+    pub max_energy: Energy,
+    pub energy_regen: Energy,
+    pub name: Option<String>,
+    pub team: Option<String>,
+    pub damage_dealt: u32,
+    pub crashed: bool,
+    pub path: Vec<Move>,
+    pub path_interrupted: bool,
+}
+
+/// The whole world between two updates, see [`crate::kikan::Kikan::snapshot`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub version: u32,
+    /// updates so far
+    pub tick: u64,
+    pub world: World,
+    /// id the next unit placed gets
+    pub next_id: UnitId,
+    pub units: Vec<UnitState>,
+    /// by the tick they resolve on
+    pub commits: BTreeMap<u64, Vec<CommitState>>,
+    /// moves waiting to be applied
+    pub moves: BTreeMap<UnitId, Position>,
+    /// what happened in the last update
+    pub events: Vec<Event>,
+    /// what happened since, reported with the next update
+    pub pending: Vec<Event>,
+}
+
+impl Snapshot {
+    pub fn load<P: AsRef<Path>>(path: P) -> KResult<Self> {
+        let text = fs::read_to_string(path)?;
+        text.parse()
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> KResult<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(&mut out, self).map_err(|e| KikanError::BadSnapshot(e.to_string()))?;
+        out.flush()?;
+        Ok(())
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("Snapshots are plain data")
+    }
+}
+
+impl std::str::FromStr for Snapshot {
+    type Err = KikanError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let value: serde_json::Value = serde_json::from_str(s).map_err(|e| KikanError::BadSnapshot(e.to_string()))?;
+        match value.get("version").and_then(|version| version.as_u64()) {
+            Some(version) if version == SNAPSHOT_VERSION as u64 => {}
+            Some(version) => return Err(KikanError::SnapshotVersion(version)),
+            None => return Err(KikanError::BadSnapshot("no version".to_string())),
+        }
+        serde_json::from_value(value).map_err(|e| KikanError::BadSnapshot(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        arsenal::{
+            engine::{EngineType, Route},
+            ModType, UnitActionContainer,
+        },
+        kikan::{Kikan, Unit},
+        map::{Map, Terrain},
+    };
+
+    fn mid_match() -> Kikan {
+        let mut kikan = Kikan::new(|| Position(0, 0));
+        let mut map = Map::new(8, 8);
+        map.add_wall(Position(2, 2)).set_terrain(Position(1, 0), Terrain::Rough);
+        kikan.set_map(map);
+        let mut unit0 = Unit::builder();
+        unit0
+            .set_engine(EngineType::Light)
+            .set_team("red".to_string())
+            .add_mods("autocannon".parse::<ModType>().unwrap().into_mod(), "gun".to_string());
+        let u0 = kikan.add_unit(Position(0, 0), unit0).unwrap();
+        let mut unit1 = Unit::builder();
+        unit1.set_engine(EngineType::Heavy).set_name("wall".to_string());
+        let u1 = kikan.add_unit(Position(0, 3), unit1).unwrap();
+
+        kikan.plan_unit_path(u0, Route::To(Position(4, 4))).unwrap();
+        for _ in 0..7 {
+            kikan.update().unwrap();
+        }
+        let target = kikan.get_unit_position(u1).unwrap();
+        kikan
+            .unit_mod_action(u0, "gun".to_string(), UnitActionContainer::Pos(target))
+            .unwrap();
+        kikan.plan_unit_move(u1, Move::N).unwrap();
+        kikan
+    }
+
+    #[test]
+    fn fork() {
+        let mut kikan = mid_match();
+        let snapshot = kikan.snapshot();
+        assert!(!snapshot.commits.is_empty());
+        assert!(!snapshot.units[0].path.is_empty());
+
+        let restored: Snapshot = snapshot.to_json().parse().unwrap();
+        assert_eq!(restored, snapshot);
+        let mut fork = Kikan::restore(&restored).unwrap();
+        assert_eq!(fork.snapshot(), snapshot);
+        for _ in 0..40 {
+            kikan.update().unwrap();
+            fork.update().unwrap();
+            assert_eq!(fork.last_events(), kikan.last_events());
+        }
+        assert_eq!(fork.snapshot(), kikan.snapshot());
+        assert!(kikan.get_unit_health(1).unwrap() < 100);
+    }
+
+    #[test]
+    fn bad_snapshots() {
+        let mut snapshot = mid_match().snapshot();
+        let newer = snapshot.to_json().replacen("\"version\":1", "\"version\":7", 1);
+        assert!(matches!(newer.parse::<Snapshot>(), Err(KikanError::SnapshotVersion(7))));
+        assert!(matches!("{}".parse::<Snapshot>(), Err(KikanError::BadSnapshot(_))));
+
+        let mut instant = snapshot.clone();
+        for commit in instant.commits.values_mut().flatten() {
+            if let CommitState::Move { delay, .. } = commit {
+                *delay = 0;
+            }
+        }
+        assert!(matches!(Kikan::restore(&instant), Err(KikanError::BadSnapshot(_))));
+
+        let far = snapshot.to_json().replacen(
+            "\"commits\":{",
+            "\"commits\":{\"18446744073709551615\":[{\"kind\":\"nothing\"}],",
+            1,
+        );
+        let far: Snapshot = far.parse().unwrap();
+        assert!(matches!(Kikan::restore(&far), Err(KikanError::BadSnapshot(_))));
+
+        let engine = snapshot.units[0].engine.clone();
+        snapshot.units[1].mods.insert("spare".to_string(), engine);
+        assert!(matches!(Kikan::restore(&snapshot), Err(KikanError::BadSnapshot(_))));
+    }
+}
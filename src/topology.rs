@@ -0,0 +1,101 @@
+use crate::{arsenal::engine::Move, error::KikanError, kikan::Position};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+const SQUARE_MOVES: [Move; 8] = Move::ALL;
+/// axial coordinates, the cells at NE and SW are two steps away
+const HEX_MOVES: [Move; 6] = [Move::N, Move::S, Move::W, Move::E, Move::NW, Move::SE];
+
+/// How cells of the world touch each other.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Topology {
+    /// eight neighbours, diagonal steps take longer
+    #[default]
+    Square,
+    /// six neighbours, all one step away
+    Hex,
+}
+
+impl Topology {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Square => "square",
+            Self::Hex => "hex",
+        }
+    }
+
+    pub fn moves(self) -> &'static [Move] {
+        match self {
+            Self::Square => &SQUARE_MOVES,
+            Self::Hex => &HEX_MOVES,
+        }
+    }
+
+    /// The cell `next_move` leads to, `None` if there is no such move here.
+    pub fn neighbour(self, from: Position, next_move: Move) -> Option<Position> {
+        self.moves().contains(&next_move).then(|| next_move.next(from))
+    }
+
+    /// The step crosses a corner of the cell and takes longer.
+    pub fn is_long(self, next_move: Move) -> bool {
+        self == Self::Square && next_move.is_diagonal()
+    }
+
+    /// Weapon range, Manhattan on squares.
+    pub fn distance(self, from: Position, to: Position) -> usize {
+        match self {
+            Self::Square => from.distance(&to),
+            Self::Hex => self.steps(from, to),
+        }
+    }
+
+    /// Fewest moves between two cells.
+    pub fn steps(self, from: Position, to: Position) -> usize {
+        let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+        (match self {
+            Self::Square => dx.unsigned_abs().max(dy.unsigned_abs()),
+            Self::Hex => (dx.unsigned_abs() + dy.unsigned_abs() + (dx + dy).unsigned_abs()) / 2,
+        }) as usize
+    }
+}
+
+impl FromStr for Topology {
+    type Err = KikanError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "square" => Self::Square,
+            "hex" => Self::Hex,
+            _ => return Err(KikanError::NoSuchTopology(s.to_string())),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hex() {
+        let hex = Topology::Hex;
+        let origin = Position(0, 0);
+        for m in hex.moves() {
+            assert_eq!(hex.steps(origin, hex.neighbour(origin, *m).unwrap()), 1);
+        }
+        assert_eq!(hex.neighbour(origin, Move::NE), None);
+        assert_eq!(hex.steps(origin, Position(1, 1)), 2);
+        assert_eq!(hex.steps(origin, Position(3, -3)), 3);
+        assert_eq!(hex.distance(origin, Position(-2, 5)), 5);
+        assert!(!hex.is_long(Move::NW));
+    }
+
+    #[test]
+    fn square() {
+        let square = Topology::Square;
+        assert_eq!(square.steps(Position(0, 0), Position(3, -2)), 3);
+        assert_eq!(square.distance(Position(0, 0), Position(3, -2)), 5);
+        assert!(square.is_long(Move::NE));
+        assert_eq!(square.moves().len(), 8);
+    }
+}
@@ -0,0 +1,349 @@
+use crossterm::{
+    cursor,
+    event::{self, Event as Input, KeyCode, KeyEventKind},
+    execute, queue,
+    style::{Color, Print, ResetColor, SetForegroundColor},
+    terminal::{self, ClearType},
+};
+use kikan::{
+    arena::{Arena, ArenaResult, Pace},
+    arsenal::CommitState,
+    error::{KResult, KikanError},
+    event::Event,
+    kikan::{Position, UnitId},
+    map::Terrain,
+    snapshot::Snapshot,
+};
+use std::{
+    collections::{BTreeSet, HashMap, HashSet, VecDeque},
+    io::{self, Write},
+    thread,
+    time::Duration,
+};
+
+const TEAM_COLOURS: [Color; 6] = [
+    Color::Red,
+    Color::Blue,
+    Color::Green,
+    Color::Yellow,
+    Color::Magenta,
+    Color::Cyan,
+];
+/// cells shown around units and shots on maps without edges
+const OPEN_MARGIN: i32 = 3;
+/// events kept on screen
+const LOG_LINES: usize = 10;
+const MAX_DELAY: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Cell {
+    glyph: char,
+    colour: Option<Color>,
+}
+
+impl Cell {
+    fn plain(glyph: char) -> Self {
+        Self { glyph, colour: None }
+    }
+}
+
+/// What the screen shows after one update.
+struct Frame {
+    tick: u64,
+    /// north first
+    rows: Vec<Vec<Cell>>,
+    units: Vec<(String, Option<Color>)>,
+}
+
+impl Frame {
+    fn new(snapshot: &Snapshot) -> Self {
+        let world = &snapshot.world;
+        let shots: HashSet<Position> = snapshot
+            .commits
+            .values()
+            .flatten()
+            .filter_map(|commit| match commit {
+                CommitState::Shot { target, .. } => Some(*target),
+                _ => None,
+            })
+            .collect();
+        let ((low_x, low_y), (high_x, high_y)) = match world.size {
+            Some((height, width)) => ((0, 0), (height as i32 - 1, width as i32 - 1)),
+            None => {
+                let cells = snapshot.units.iter().map(|unit| unit.pos).chain(shots.iter().copied());
+                let (xs, ys): (Vec<i32>, Vec<i32>) = cells.map(|pos| (pos.0, pos.1)).unzip();
+                let low = (xs.iter().min().unwrap_or(&0), ys.iter().min().unwrap_or(&0));
+                let high = (xs.iter().max().unwrap_or(&0), ys.iter().max().unwrap_or(&0));
+                (
+                    (low.0 - OPEN_MARGIN, low.1 - OPEN_MARGIN),
+                    (high.0 + OPEN_MARGIN, high.1 + OPEN_MARGIN),
+                )
+            }
+        };
+
+        let walls: HashSet<Position> = world.walls.iter().copied().collect();
+        let terrain: HashMap<Position, Terrain> = world.terrain.iter().copied().collect();
+        let teams: BTreeSet<&str> = snapshot.units.iter().filter_map(|unit| unit.team.as_deref()).collect();
+        let colour = |id: UnitId, team: Option<&str>| {
+            let i = match team {
+                Some(team) => teams.iter().position(|t| *t == team).unwrap_or(0),
+                None => teams.len() + id as usize,
+            };
+            TEAM_COLOURS[i % TEAM_COLOURS.len()]
+        };
+        let mut units: HashMap<Position, Cell> = HashMap::new();
+        let mut list = Vec::new();
+        for unit in snapshot.units.iter() {
+            let team = unit.team.as_deref();
+            let cell = if unit.health == 0 {
+                Cell {
+                    glyph: 'x',
+                    colour: Some(Color::DarkGrey),
+                }
+            } else {
+                Cell {
+                    glyph: std::char::from_digit(unit.id % 36, 36).unwrap_or('?'),
+                    colour: Some(colour(unit.id, team)),
+                }
+            };
+            // the living are drawn over wrecks
+            if unit.health > 0 || !units.contains_key(&unit.pos) {
+                units.insert(unit.pos, cell);
+            }
+            let state = if unit.health == 0 {
+                "destroyed".to_string()
+            } else {
+                format!("hp {} energy {}", unit.health, unit.energy)
+            };
+            let team = team.map(|team| format!(" [{}]", team)).unwrap_or_default();
+            let name = unit.name.as_deref().unwrap_or("?");
+            list.push((
+                format!("{} #{} {}{} {}", cell.glyph, unit.id, name, team, state),
+                cell.colour,
+            ));
+        }
+
+        let rows = (low_x..=high_x)
+            .rev()
+            .map(|x| {
+                (low_y..=high_y)
+                    .map(|y| {
+                        let pos = Position(x, y);
+                        if let Some(cell) = units.get(&pos) {
+                            *cell
+                        } else if shots.contains(&pos) {
+                            Cell {
+                                glyph: '*',
+                                colour: Some(Color::White),
+                            }
+                        } else if walls.contains(&pos) {
+                            Cell::plain('#')
+                        } else {
+                            Cell::plain(match terrain.get(&pos).copied().unwrap_or_default() {
+                                Terrain::Open => '.',
+                                Terrain::Rough => ',',
+                                Terrain::Mud => '%',
+                                Terrain::Road => '=',
+                                Terrain::Water => '~',
+                            })
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+        Self {
+            tick: snapshot.tick,
+            rows,
+            units: list,
+        }
+    }
+
+    #[cfg(test)]
+    fn text(&self) -> Vec<String> {
+        self.rows
+            .iter()
+            .map(|row| row.iter().map(|cell| cell.glyph).collect())
+            .collect()
+    }
+}
+
+struct Screen {
+    out: io::Stdout,
+    log: VecDeque<String>,
+}
+
+impl Screen {
+    fn open() -> KResult<Self> {
+        let mut out = io::stdout();
+        terminal::enable_raw_mode()?;
+        execute!(out, terminal::EnterAlternateScreen, cursor::Hide)?;
+        Ok(Self {
+            out,
+            log: VecDeque::new(),
+        })
+    }
+
+    fn note(&mut self, tick: u64, events: &[Event]) {
+        for event in events {
+            if self.log.len() == LOG_LINES {
+                self.log.pop_front();
+            }
+            self.log.push_back(format!("{:>5} {}", tick, event));
+        }
+    }
+
+    fn draw(&mut self, frame: &Frame, status: &str) -> KResult<()> {
+        let out = &mut self.out;
+        queue!(out, terminal::Clear(ClearType::All), cursor::MoveTo(0, 0))?;
+        let mut line = 0;
+        let mut next_line = |out: &mut io::Stdout| {
+            line += 1;
+            queue!(out, cursor::MoveTo(0, line))
+        };
+        queue!(out, Print(format!("tick {}  {}", frame.tick, status)))?;
+        next_line(out)?;
+        for row in frame.rows.iter() {
+            next_line(out)?;
+            for cell in row {
+                match cell.colour {
+                    Some(colour) => queue!(out, SetForegroundColor(colour), Print(cell.glyph), ResetColor)?,
+                    None => queue!(out, Print(cell.glyph))?,
+                }
+            }
+        }
+        next_line(out)?;
+        for (unit, colour) in frame.units.iter() {
+            next_line(out)?;
+            match colour {
+                Some(colour) => queue!(out, SetForegroundColor(*colour), Print(unit), ResetColor)?,
+                None => queue!(out, Print(unit))?,
+            }
+        }
+        next_line(out)?;
+        for event in self.log.iter() {
+            next_line(out)?;
+            queue!(out, Print(event))?;
+        }
+        out.flush()?;
+        Ok(())
+    }
+}
+
+impl Drop for Screen {
+    fn drop(&mut self) {
+        execute!(self.out, cursor::Show, terminal::LeaveAlternateScreen).ok();
+        terminal::disable_raw_mode().ok();
+    }
+}
+
+fn status(pace: &Pace, over: bool) -> String {
+    if over {
+        return "match over, any key to leave".to_string();
+    }
+    let state = if pace.is_paused() { "paused" } else { "running" };
+    format!(
+        "{}, {} ms a tick   space pause  n step  + faster  - slower  q quit",
+        state,
+        pace.delay().as_millis()
+    )
+}
+
+/// Runs the match on screen, a tick every `delay` until paused or sped up.
+pub(crate) fn watch(mut arena: Arena, delay: Duration) -> KResult<ArenaResult> {
+    let pace = Pace::new(delay);
+    arena.set_pace(pace.clone());
+    let world = arena.world();
+    let mut updates = world.lock().unwrap().wait_for_update();
+    let run = thread::spawn(move || arena.run());
+
+    let mut screen = Screen::open()?;
+    let mut frame = Frame::new(&world.lock().unwrap().snapshot());
+    screen.draw(&frame, &status(&pace, false))?;
+    let mut over = false;
+    let mut quit = false;
+    loop {
+        let mut changed = false;
+        if event::poll(Duration::from_millis(15))? {
+            if let Input::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Press {
+                    if over {
+                        break;
+                    }
+                    match key.code {
+                        KeyCode::Char('q') | KeyCode::Esc => {
+                            pace.stop();
+                            quit = true;
+                        }
+                        KeyCode::Char(' ') => pace.set_paused(!pace.is_paused()),
+                        KeyCode::Char('n') | KeyCode::Char('.') => {
+                            pace.set_paused(true);
+                            pace.step();
+                        }
+                        KeyCode::Char('+') => pace.set_delay(pace.delay() / 2),
+                        KeyCode::Char('-') => {
+                            pace.set_delay((pace.delay() * 2).clamp(Duration::from_millis(1), MAX_DELAY))
+                        }
+                        _ => {}
+                    }
+                    changed = true;
+                }
+            }
+        }
+        // ticks the screen was too slow for are skipped, their events still logged
+        while let Some(tick) = updates.try_recv() {
+            screen.note(tick.tick, &tick.events);
+            changed = true;
+        }
+        // stopped on purpose, nothing left to look at
+        if quit && run.is_finished() {
+            break;
+        }
+        if !over && run.is_finished() {
+            over = true;
+            changed = true;
+        }
+        if changed {
+            frame = Frame::new(&world.lock().unwrap().snapshot());
+            screen.draw(&frame, &status(&pace, over))?;
+        }
+    }
+    drop(screen);
+    run.join().unwrap_or(Err(KikanError::MatchOver))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use kikan::{
+        arsenal::{engine::EngineType, ModType, UnitActionContainer},
+        kikan::{Kikan, Unit},
+        map::Map,
+    };
+
+    #[test]
+    fn frame() {
+        let mut kikan = Kikan::new(|| Position(0, 0));
+        let mut map = Map::new(3, 4);
+        map.add_wall(Position(2, 0)).set_terrain(Position(0, 3), Terrain::Water);
+        kikan.set_map(map);
+        let mut unit = Unit::builder();
+        unit.set_engine(EngineType::STE)
+            .set_team("red".to_string())
+            .add_mods("railgun".parse::<ModType>().unwrap().into_mod(), "gun".to_string());
+        let shooter = kikan.add_unit(Position(0, 0), unit).unwrap();
+        let mut unit = Unit::builder();
+        unit.set_engine(EngineType::STE).set_health(10);
+        let wreck = kikan.add_unit(Position(1, 2), unit).unwrap();
+        kikan.damage_unit(wreck, 10, None).unwrap();
+        kikan
+            .unit_mod_action(shooter, "gun".to_string(), UnitActionContainer::Pos(Position(2, 3)))
+            .unwrap();
+
+        let frame = Frame::new(&kikan.snapshot());
+        assert_eq!(frame.text(), vec!["#..*", "..x.", "0..~"]);
+        assert_eq!(frame.units.len(), 2);
+        assert!(frame.units[0].0.starts_with("0 #0 ? [red] hp 100"));
+        assert!(frame.units[1].0.ends_with("destroyed"));
+        assert_eq!(frame.rows[2][0].colour, Some(TEAM_COLOURS[0]));
+    }
+}